@@ -0,0 +1,329 @@
+//! Build cache for the Blog Builder.
+//!
+//! The cache manifest records a hash of every input of each page when
+//! it was last compiled, so that pages whose inputs have not changed
+//! can be skipped by later builds.  It is written to the output
+//! directory if there is one, or else to the site root.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+
+use crate::{
+    emitter::History,
+    Error,
+    Metadata,
+    ParserBuilder,
+    Renderers,
+    VERSION,
+};
+
+/// Name of the cache manifest.
+pub const CACHE_FILENAME: &str = ".blog-cache";
+
+/// First line of the cache manifest, identifying its format.
+const HEADER: &str = "blog-cache 3";
+
+/// Hash recorded for a file that cannot be read, so that a page is
+/// compiled again once the file appears.
+const MISSING: u64 = 0;
+
+/// Hashes of the inputs of a page when it was last compiled.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Entry {
+    /// Hash of the page source.
+    source: u64,
+
+    /// File the page was written to.
+    output: PathBuf,
+
+    /// Files read by the page, and their hashes.
+    dependencies: Vec<(PathBuf, u64)>,
+
+    /// Date shown on the page, if it was found from the page's files,
+    /// such as its last commit, rather than its front matter.
+    date: Option<NaiveDate>,
+}
+
+/// Hashes of the inputs of every page, as of the last build.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cache {
+    /// Hash of the crate version, the plugins and the settings shared by
+    /// every page, such as the menu, analytics and style.
+    settings: u64,
+
+    /// Entry for each page, by path relative to the site root.
+    pages: HashMap<PathBuf, Entry>,
+}
+
+impl Cache {
+    /// Loads the cache manifest of a site.
+    ///
+    /// A missing or malformed manifest, or one written with different
+    /// settings or plugins, gives an empty cache, so that every page
+    /// is compiled.
+    pub fn load(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Self {
+        let settings = Self::hash_settings(metadata, parser, renderers);
+
+        let contents = fs::read_to_string(Self::path(metadata))
+            .unwrap_or_default();
+
+        match Self::parse(&contents) {
+            Some (cache) if cache.settings == settings => cache,
+            _ => Self {
+                settings,
+                pages: HashMap::new(),
+            },
+        }
+    }
+
+    /// Parses the contents of a cache manifest.
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+
+        let settings = u64::from_str_radix(lines.next()?.strip_prefix("settings ")?, 16).ok()?;
+        let mut pages = HashMap::new();
+        let mut page: Option<(PathBuf, Entry)> = None;
+
+        for line in lines {
+            // The output, date and dependencies are indented below their page
+            let (indented, line) = match line.strip_prefix("    ") {
+                Some (l) => (true, l),
+                None => (false, line),
+            };
+            let (first, path) = line.split_once(' ')?;
+
+            if indented && first == "output" {
+                page.as_mut()?.1.output = PathBuf::from(path);
+                continue;
+            }
+            if indented && first == "date" {
+                page.as_mut()?.1.date = Some (path.parse().ok()?);
+                continue;
+            }
+
+            let hash = u64::from_str_radix(first, 16).ok()?;
+            if indented {
+                page.as_mut()?.1.dependencies.push((PathBuf::from(path), hash));
+            } else {
+                pages.extend(page.take());
+                page = Some ((PathBuf::from(path), Entry {
+                    source: hash,
+                    ..Entry::default()
+                }));
+            }
+        }
+        pages.extend(page);
+
+        Some (Self {
+            settings,
+            pages,
+        })
+    }
+
+    /// Writes the cache manifest.
+    pub fn save(&self, metadata: &Metadata) -> Result<(), Error> {
+        let mut pages = self.pages.iter().collect::<Vec<(&PathBuf, &Entry)>>();
+        pages.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut output = format!("{}\nsettings {:016x}\n", HEADER, self.settings);
+        for (page, entry) in pages {
+            output.push_str(&format!("{:016x} {}\n", entry.source, page.display()));
+            output.push_str(&format!("    output {}\n", entry.output.display()));
+            if let Some (date) = entry.date {
+                output.push_str(&format!("    date {}\n", date));
+            }
+            for (dependency, hash) in &entry.dependencies {
+                output.push_str(&format!("    {:016x} {}\n", hash, dependency.display()));
+            }
+        }
+
+        let path = Self::path(metadata);
+        match fs::write(&path, output) {
+            Ok (_) => Ok (()),
+            Err (e) => Err (Error::CannotWriteFile {
+                path,
+                source: e,
+            }),
+        }
+    }
+
+    /// Checks whether a page, the files it reads and the date it shows
+    /// are unchanged since it was last compiled, and its output still exists.
+    ///
+    /// Dates found from git are looked up in the history of the site,
+    /// which is read once for the whole build.
+    pub fn is_fresh(&self, metadata: &Metadata, page: &Path, history: &History) -> bool {
+        let entry = match self.pages.get(&Self::key(metadata, page)) {
+            Some (e) => e,
+            None => return false,
+        };
+
+        // Finding the date may run git, so it is checked last
+        entry.output.is_file()
+            && hash_file(page) == entry.source
+            && entry.dependencies.iter().all(|(p, h)| hash_file(p) == *h)
+            && entry.date.is_none_or(|d| history.last_updated(page, metadata.get_source_date_epoch()) == d)
+    }
+
+    /// Records the inputs of a page that has just been compiled, given
+    /// the files that it read, the file it was written to and the date
+    /// it shows, if that was found from its files.
+    pub fn record(&mut self, metadata: &Metadata, page: &Path, dependencies: Vec<PathBuf>, output: PathBuf, date: Option<NaiveDate>) {
+        let dependencies = dependencies.into_iter()
+            .map(|p| (p.to_owned(), hash_file(&p)))
+            .collect();
+
+        self.pages.insert(Self::key(metadata, page), Entry {
+            source: hash_file(page),
+            output,
+            dependencies,
+            date,
+        });
+    }
+
+    /// Forgets a page, so that it is compiled by the next build.
+    pub fn remove(&mut self, metadata: &Metadata, page: &Path) {
+        self.pages.remove(&Self::key(metadata, page));
+    }
+
+    /// Gets the path of the cache manifest, in the output directory
+    /// if there is one, or else in the site root.
+    fn path(metadata: &Metadata) -> PathBuf {
+        metadata.get_out()
+            .unwrap_or_else(|| metadata.get_root())
+            .join(CACHE_FILENAME)
+    }
+
+    /// Gets the key of a page, its path relative to the site root.
+    fn key(metadata: &Metadata, page: &Path) -> PathBuf {
+        let root = metadata.get_root();
+        page.strip_prefix(&root)
+            .unwrap_or(page)
+            .to_path_buf()
+    }
+
+    /// Hashes the crate version, the plugins and every setting that
+    /// affects the output of all pages.
+    fn hash_settings(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> u64 {
+        let settings = [
+            Some (VERSION.to_string()),
+            metadata.get_style(),
+            metadata.get_stylesheet(),
+            metadata.get_links(),
+            metadata.get_sitename(),
+            metadata.get_menu(),
+            Some (metadata.is_icon().to_string()),
+            metadata.get_analytics(),
+            metadata.get_macros().map(|(_, m)| m),
+            Some (metadata.get_lang()),
+            metadata.get_out().map(|o| o.display().to_string()),
+            metadata.get_source_date_epoch().map(|s| s.to_string()),
+        ];
+        let templates = metadata.get_templates()
+            .get_sources()
+            .iter()
+            .flat_map(|(name, source)| [Some (name.to_owned()), Some (source.to_owned())]);
+        let plugins = parser.get_versions()
+            .into_iter()
+            .chain(renderers.get_versions())
+            .map(Some);
+
+        // Each setting is prefixed by its length, so that
+        // adjacent settings cannot run together
+        let mut bytes = Vec::new();
+        for setting in settings.into_iter().chain(templates).chain(plugins) {
+            let setting = setting.unwrap_or_default();
+            bytes.extend(setting.len().to_le_bytes());
+            bytes.extend(setting.as_bytes());
+        }
+
+        hash(&bytes)
+    }
+}
+
+/// Hashes the contents of a file, or gives `MISSING` if it cannot be read.
+fn hash_file(path: &Path) -> u64 {
+    fs::read(path).map_or(MISSING, |b| hash(&b))
+}
+
+/// Hashes bytes with 64-bit FNV-1a, which is stable across builds and platforms.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[test]
+fn cache_manifest() {
+    let site = crate::test_dir::TestDir::with_code_page("cache-manifest");
+    site.write("a.html", "");
+
+    let args = ["build".to_string(), site.path().display().to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+    let page = site.join("a.txt");
+    let parser = crate::Parser::builder();
+    let renderers = Renderers::new();
+    let history = History::new(site.path());
+
+    let mut cache = Cache::load(&metadata, &parser, &renderers);
+    assert!(!cache.is_fresh(&metadata, &page, &history));
+    cache.record(&metadata, &page, vec![site.join("main.rs")], site.join("a.html"), None);
+    assert!(cache.is_fresh(&metadata, &page, &history));
+
+    cache.save(&metadata).unwrap();
+    assert_eq!(Cache::load(&metadata, &parser, &renderers), cache);
+    assert!(Cache::load(&metadata, &parser, &renderers).is_fresh(&metadata, &page, &history));
+
+    // Changing a dependency, the settings or the plugins invalidates the page
+    struct Badge;
+
+    impl crate::Renderer for Badge {
+        fn render(&self, _: &crate::Extension, _: &[String]) -> Result<String, Error> {
+            Ok (String::new())
+        }
+    }
+
+    let mut plugins = Renderers::new();
+    plugins.insert("badge", Badge);
+    assert!(!Cache::load(&metadata, &parser, &plugins).is_fresh(&metadata, &page, &history));
+
+    fs::write(site.join("main.rs"), "fn main() { }").unwrap();
+    assert!(!cache.is_fresh(&metadata, &page, &history));
+    cache.record(&metadata, &page, vec![site.join("main.rs")], site.join("a.html"), None);
+    cache.save(&metadata).unwrap();
+
+    let args = ["build".to_string(), site.path().display().to_string(), "--lang".to_string(), "fr".to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+    assert!(!Cache::load(&metadata, &parser, &renderers).is_fresh(&metadata, &page, &history));
+
+    // So does a missing dependency appearing, or a change to the date shown
+    let mut cache = Cache::load(&metadata, &parser, &renderers);
+    cache.record(&metadata, &page, vec![site.join("missing.rs")], site.join("a.html"), None);
+    assert!(cache.is_fresh(&metadata, &page, &history));
+    site.write("missing.rs", "");
+    assert!(!cache.is_fresh(&metadata, &page, &history));
+
+    cache.record(&metadata, &page, Vec::new(), site.join("a.html"), NaiveDate::from_ymd_opt(2000, 1, 1));
+    cache.save(&metadata).unwrap();
+    assert_eq!(Cache::load(&metadata, &parser, &renderers), cache);
+    assert!(!cache.is_fresh(&metadata, &page, &history));
+
+    // With an output directory, the manifest is kept there
+    let out = site.join("public");
+    fs::create_dir(&out).unwrap();
+    let args = ["build".to_string(), site.path().display().to_string(), "--out".to_string(), out.display().to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+    Cache::load(&metadata, &parser, &renderers).save(&metadata).unwrap();
+    assert!(out.join(CACHE_FILENAME).is_file());
+}
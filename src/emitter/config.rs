@@ -0,0 +1,236 @@
+//! Site configuration file for the Blog Builder emitter module.
+
+use std::{
+    env,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use toml::{
+    Table,
+    Value,
+};
+
+use crate::Error;
+
+/// Name of the site configuration file.
+pub const CONFIG_FILENAME: &str = "blog.toml";
+
+/// Keys accepted in the site configuration file.
+const KEYS: [&str; 10] = ["sitename", "style", "menu", "icon", "analytics", "lang", "out", "templates", "partials", "macros"];
+
+#[derive(Clone, Debug, Default)]
+/// Site configuration, read from `blog.toml` and overridden by command-line flags.
+pub struct Config {
+    /// Directory containing the configuration file.
+    pub root: Option<PathBuf>,
+
+    /// Website name.
+    pub sitename: Option<String>,
+
+    /// Stylesheet name.
+    pub style: Option<String>,
+
+    /// HTML file included as the menu.
+    pub menu: Option<PathBuf>,
+
+    /// Whether to include an icon.
+    pub icon: Option<bool>,
+
+    /// HTML file with analytics links.
+    pub analytics: Option<PathBuf>,
+
+    /// Language of the website.
+    pub lang: Option<String>,
+
+    /// Output directory.
+    pub out: Option<PathBuf>,
+
+    /// Directory containing layout templates.
+    pub templates: Option<PathBuf>,
+
+    /// Directory containing partial pages, which are included
+    /// in other pages rather than compiled.
+    pub partials: Option<PathBuf>,
+
+    /// File of macros available to every page.
+    pub macros: Option<PathBuf>,
+}
+
+impl Config {
+    /// Finds and loads the configuration file in the directory of the input
+    /// or in any of its parent directories.
+    pub fn discover(input: &Path) -> Result<Option<Self>, Error> {
+        let start = if input.is_dir() {
+            input
+        } else {
+            input.parent().unwrap_or(input)
+        };
+
+        // A relative path such as `.` has no parents of its own, so walk
+        // up from its absolute path, but keep the configuration file's
+        // path relative to the working directory
+        let (start, cwd) = match (start.canonicalize(), env::current_dir()) {
+            (Ok (s), Ok (c)) if start.is_relative() => (s, c.canonicalize().unwrap_or(c)),
+            _ => (start.to_path_buf(), PathBuf::new()),
+        };
+
+        for dir in start.ancestors() {
+            let path = relative_path(dir, &cwd).join(CONFIG_FILENAME);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+
+        Ok (None)
+    }
+
+    /// Loads a configuration file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok (c) => c,
+            Err (e) => return Err (Error::CannotReadFile {
+                path: path.to_path_buf(),
+                source: e,
+            }),
+        };
+
+        let root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        Self::parse(&contents, &root).map_err(|message| Error::InvalidConfig {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Parses the contents of a configuration file.  Paths are resolved
+    /// relative to `root`, the directory containing the file.
+    fn parse(contents: &str, root: &Path) -> Result<Self, String> {
+        let table = match contents.parse::<Table>() {
+            Ok (t) => t,
+            Err (e) => return Err (e.to_string().trim_end().to_string()),
+        };
+
+        let mut config = Self {
+            root: Some (root.to_path_buf()),
+            ..Self::default()
+        };
+
+        for (key, value) in table {
+            match key.as_str() {
+                "sitename" => config.sitename = Some (string(&key, value)?),
+                "style" => config.style = Some (string(&key, value)?),
+                "menu" => config.menu = Some (root.join(string(&key, value)?)),
+                "icon" => config.icon = Some (boolean(&key, value)?),
+                "analytics" => config.analytics = Some (root.join(string(&key, value)?)),
+                "lang" => config.lang = Some (string(&key, value)?),
+                "out" => config.out = Some (root.join(string(&key, value)?)),
+                "templates" => config.templates = Some (root.join(string(&key, value)?)),
+                "partials" => config.partials = Some (root.join(string(&key, value)?)),
+                "macros" => config.macros = Some (root.join(string(&key, value)?)),
+                _ => return Err (unknown_key(&key, &KEYS)),
+            }
+        }
+
+        Ok (config)
+    }
+}
+
+/// Expresses an absolute path relative to an absolute base directory,
+/// such as `..` for `/home/site` relative to `/home/site/posts`.
+/// Relative paths are returned unchanged.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    if !path.is_absolute() || !base.is_absolute() {
+        return path.to_path_buf();
+    }
+
+    let common = path.components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    base.components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .chain(path.components().skip(common))
+        .collect()
+}
+
+/// Describes an unknown key, suggesting a known key with a similar spelling.
+pub(crate) fn unknown_key(key: &str, keys: &[&str]) -> String {
+    let suggestion = keys.iter()
+        .map(|k| (distance(key, k), k))
+        .filter(|(d, _)| *d <= 2)
+        .min();
+
+    match suggestion {
+        Some ((_, k)) => format!("unknown key `{}` (did you mean `{}`?)", key, k),
+        None => format!("unknown key `{}` (expected one of: {})", key, keys.join(", ")),
+    }
+}
+
+/// Gets a string value, or returns an error.
+pub(crate) fn string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String (s) => Ok (s),
+        v => Err (format!("key `{}` must be a string, found {}", key, v.type_str())),
+    }
+}
+
+/// Gets a boolean value, or returns an error.
+pub(crate) fn boolean(key: &str, value: Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean (b) => Ok (b),
+        v => Err (format!("key `{}` must be a boolean, found {}", key, v.type_str())),
+    }
+}
+
+/// Computes the edit distance between two strings.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[test]
+fn parse_config() {
+    let root = Path::new("site");
+    let config = Config::parse("sitename = \"My Site\"\nstyle = \"tech\"\nmenu = \"menu.html\"\nicon = true\n", root).unwrap();
+    assert_eq!(config.sitename.as_deref(), Some ("My Site"));
+    assert_eq!(config.menu, Some (root.join("menu.html")));
+    assert_eq!(config.icon, Some (true));
+
+    assert_eq!(
+        Config::parse("stlye = \"tech\"", root).unwrap_err(),
+        "unknown key `stlye` (did you mean `style`?)",
+    );
+    assert_eq!(
+        Config::parse("icon = \"yes\"", root).unwrap_err(),
+        "key `icon` must be a boolean, found string",
+    );
+}
+
+#[test]
+fn discover_config() {
+    assert_eq!(relative_path(Path::new("/home/site"), Path::new("/home/site/posts")), PathBuf::from(".."));
+    assert_eq!(relative_path(Path::new("/home/site"), Path::new("/home/site")), PathBuf::new());
+    assert_eq!(relative_path(Path::new("/srv/site"), Path::new("/home")), PathBuf::from("../srv/site"));
+
+    let site = crate::test_dir::TestDir::new("discover-config");
+    site.write(CONFIG_FILENAME, "sitename = \"Site\"");
+    site.write("posts/drafts/a.txt", "~ Page");
+
+    let config = Config::discover(&site.join("posts/drafts/a.txt")).unwrap().unwrap();
+    assert_eq!(config.root, Some (site.path().to_path_buf()));
+    assert_eq!(config.sitename.as_deref(), Some ("Site"));
+}
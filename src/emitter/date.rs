@@ -0,0 +1,244 @@
+//! "Last Updated" dates for the Blog Builder emitter module.
+//!
+//! Dates are computed in UTC, so that builds on machines in different
+//! time zones agree.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+    time::UNIX_EPOCH,
+};
+
+use chrono::prelude::*;
+
+/// Environment variable which, if set to a number of seconds since the
+/// Unix epoch, overrides the dates of pages that do not set their own,
+/// so that builds are reproducible byte for byte.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Gets the date on which a page was last updated, from the first
+/// of these that is available:
+///
+/// 1. `explicit`, the date set in the page's front matter
+/// 2. `source_date_epoch`, read from `SOURCE_DATE_EPOCH`
+/// 3. the last commit touching the page, if it is in a git repository
+///    and has no uncommitted changes
+/// 4. the modification time of the page
+/// 5. the current date
+pub fn last_updated(source: &Path, explicit: Option<NaiveDate>, source_date_epoch: Option<i64>) -> NaiveDate {
+    explicit
+        .or_else(|| source_date_epoch.and_then(from_timestamp))
+        .or_else(|| from_git(source))
+        .or_else(|| from_modified(source))
+        .unwrap_or_else(|| Utc::now().date_naive())
+}
+
+/// History of the git repository containing a site, read at most once
+/// however many pages are dated with it.
+#[derive(Debug)]
+pub struct History {
+    /// Directory within the repository.
+    dir: PathBuf,
+
+    /// Commits of the repository, once read, or `None` if the
+    /// directory is not in a repository.
+    commits: OnceLock<Option<Commits>>,
+}
+
+/// Dates of the last commits of the files in a git repository.
+#[derive(Debug)]
+struct Commits {
+    /// Root of the repository.
+    root: PathBuf,
+
+    /// Files with uncommitted changes, or that are not committed.
+    changed: HashSet<PathBuf>,
+
+    /// Date of the last commit touching each file.
+    dates: HashMap<PathBuf, NaiveDate>,
+}
+
+impl History {
+    /// Prepares to read the history of the repository containing a
+    /// directory, which is only read once a date is needed from it.
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            commits: OnceLock::new(),
+        }
+    }
+
+    /// Gets the date on which a page that does not set its own date was
+    /// last updated, as `last_updated` does, but without running git for
+    /// each page.
+    pub fn last_updated(&self, source: &Path, source_date_epoch: Option<i64>) -> NaiveDate {
+        source_date_epoch.and_then(from_timestamp)
+            .or_else(|| self.last_commit(source))
+            .or_else(|| from_modified(source))
+            .unwrap_or_else(|| Utc::now().date_naive())
+    }
+
+    /// Gets the date of the last commit touching a file, unless the file
+    /// is not committed or has been changed since.
+    fn last_commit(&self, source: &Path) -> Option<NaiveDate> {
+        let commits = self.commits.get_or_init(|| Commits::read(&self.dir)).as_ref()?;
+        let path = fs::canonicalize(source).ok()?;
+
+        // Files in other repositories, such as submodules, are asked about one by one
+        match path.strip_prefix(&commits.root) {
+            Ok (p) if commits.changed.contains(p) => None,
+            Ok (p) => commits.dates.get(p).copied(),
+            Err (_) => from_git(source),
+        }
+    }
+}
+
+impl Commits {
+    /// Reads the status and log of the repository containing a directory.
+    fn read(dir: &Path) -> Option<Self> {
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(dir)
+                .args(args)
+                .output()
+                .ok()?;
+
+            if output.status.success() {
+                Some (String::from_utf8_lossy(&output.stdout).into_owned())
+            } else {
+                None
+            }
+        };
+
+        let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+        let root = fs::canonicalize(&root).unwrap_or(root);
+
+        // Each entry is a status and a path, followed by the original
+        // path if the file was renamed or copied, which has changed too
+        let mut changed = HashSet::new();
+        let status = git(&["status", "--porcelain", "-z"])?;
+        let mut entries = status.split('\0').filter(|e| !e.is_empty());
+        while let Some (entry) = entries.next() {
+            let (code, path) = entry.split_at(entry.len().min(3));
+            changed.insert(PathBuf::from(path));
+            if code.contains(['R', 'C']) {
+                changed.extend(entries.next().map(PathBuf::from));
+            }
+        }
+
+        // Commits are listed newest first, each as its timestamp followed
+        // by the files it touched.  Paths in a repository never begin
+        // with `/`, so it marks the timestamps.
+        let mut dates = HashMap::new();
+        let mut date = None;
+        for field in git(&["log", "-z", "--format=/%ct", "--name-only"])?.split('\0') {
+            let field = field.trim_start_matches('\n');
+            if let Some (timestamp) = field.strip_prefix('/') {
+                date = timestamp.parse().ok().and_then(from_timestamp);
+            } else if let (false, Some (d)) = (field.is_empty(), date) {
+                dates.entry(PathBuf::from(field)).or_insert(d);
+            }
+        }
+
+        Some (Self {
+            root,
+            changed,
+            dates,
+        })
+    }
+}
+
+/// Formats a date as it is displayed on a page.
+pub fn format(date: NaiveDate) -> String {
+    date.format("%A, %B %d, %Y").to_string()
+}
+
+/// Gets the date of a number of seconds since the Unix epoch.
+fn from_timestamp(seconds: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp(seconds, 0).map(|d| d.date_naive())
+}
+
+/// Gets the date of the last commit touching a file, unless the file
+/// is not committed or has been changed since.
+fn from_git(source: &Path) -> Option<NaiveDate> {
+    let dir = match source.parent() {
+        Some (d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let name = source.file_name()?;
+
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(args)
+            .arg("--")
+            .arg(name)
+            .output()
+            .ok()?;
+
+        if output.status.success() {
+            Some (String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            None
+        }
+    };
+
+    if !git(&["status", "--porcelain"])?.is_empty() {
+        return None;
+    }
+
+    from_timestamp(git(&["log", "-1", "--format=%ct"])?.parse().ok()?)
+}
+
+/// Gets the date on which a file was last modified.
+fn from_modified(source: &Path) -> Option<NaiveDate> {
+    let modified = fs::metadata(source).and_then(|m| m.modified()).ok()?;
+    let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    from_timestamp(seconds.try_into().ok()?)
+}
+
+#[test]
+fn date_sources() {
+    let dir = crate::test_dir::TestDir::new("date-sources");
+    let page = dir.write("page.txt", "~Page");
+
+    // 2001-09-09T01:46:40Z
+    assert_eq!(format(last_updated(&page, None, Some (1_000_000_000))), "Sunday, September 09, 2001");
+    assert_eq!(last_updated(&page, None, None), from_modified(&page).unwrap());
+
+    let explicit = NaiveDate::from_ymd_opt(2024, 1, 31);
+    assert_eq!(last_updated(&page, explicit, Some (1_000_000_000)), explicit.unwrap());
+}
+
+#[test]
+fn history_dates() {
+    let dir = crate::test_dir::TestDir::new("history-dates");
+    let committed = dir.write("posts/a page.txt", "~Committed");
+    let changed = dir.write("changed.txt", "~Changed");
+    let git = |args: &[&str]| Command::new("git")
+        .current_dir(dir.path())
+        .args(["-c", "user.name=Blog", "-c", "user.email=blog@example.com"])
+        .args(args)
+        .env("GIT_COMMITTER_DATE", "1000000000 +0000")
+        .output()
+        .is_ok_and(|o| o.status.success());
+
+    // Git is not needed to build a site, so it may be missing
+    if !git(&["init", "--quiet"]) {
+        return;
+    }
+    assert!(git(&["add", "."]));
+    assert!(git(&["commit", "--quiet", "--message", "Add pages"]));
+    fs::write(&changed, "~Changed again").unwrap();
+    let untracked = dir.write("untracked.txt", "~Untracked");
+
+    // 2001-09-09T01:46:40Z
+    let history = History::new(dir.path());
+    assert_eq!(format(history.last_updated(&committed, None)), "Sunday, September 09, 2001");
+    for page in [&committed, &changed, &untracked] {
+        assert_eq!(history.last_updated(page, None), last_updated(page, None, None));
+    }
+}
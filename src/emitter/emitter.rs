@@ -1,24 +1,195 @@
 //! Emitter for the Blog Builder.
 
-use super::Metadata;
+use std::{
+    cell::Cell,
+    path::Path,
+};
 
-use crate::Expression;
+use chrono::NaiveDate;
+
+use super::{
+    date,
+    escape,
+    Context,
+    Metadata,
+    Renderer,
+    Renderers,
+    Template,
+    Value,
+};
+
+use crate::{
+    Error,
+    Expression,
+    PageMeta,
+};
 
 pub struct Emitter {
     metadata: Metadata,
+    renderers: Renderers,
+
+    /// Date shown on the last page emitted, if it was found from the
+    /// page's files rather than its front matter.
+    found_date: Cell<Option<NaiveDate>>,
 }
 
 impl Emitter {
     pub fn new(metadata: &Metadata) -> Self {
         Self {
             metadata: metadata.to_owned(),
+            renderers: Renderers::new(),
+            found_date: Cell::new(None),
         }
     }
 
-    pub fn emit(&self, expressions: Vec<Expression>, pagename: &str) -> Vec<u8> {
-        let mut output = String::new();
+    /// Renders extensions with the given renderers, in addition to any
+    /// already registered.
+    pub fn with_renderers(mut self, renderers: &Renderers) -> Self {
+        self.renderers.extend(renderers);
+        self
+    }
+
+    /// Renders extensions of the given name with the given renderer.
+    pub fn renderer(mut self, name: &str, renderer: impl Renderer + 'static) -> Self {
+        self.renderers.insert(name, renderer);
+        self
+    }
+
+    pub fn emit(&self, mut expressions: Vec<Expression>, meta: &PageMeta) -> Result<Vec<u8>, Error> {
+        self.found_date.set(None);
+
+        let mut errors = Vec::new();
+
+        // The menu, date and footnotes are filled in as HTML before rendering,
+        // so that neither raw HTML in the page nor a template is mistaken for them
+        let mut footnotes = Vec::new();
+        for expr in &mut expressions {
+            self.fill_in(expr, meta, &mut footnotes);
+        }
+        match self.footnotes(&footnotes) {
+            Ok (f) => for expr in &mut expressions {
+                Self::fill_in_footnotes(expr, &f);
+            },
+            Err (e) => errors.push(e),
+        }
+
+        let mut content = String::new();
+        for expr in expressions {
+            match expr.render_with(&self.renderers) {
+                Ok (s) => content.push_str(&s),
+                Err (e) => errors.push(e),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err (Error::from_list(errors));
+        }
+
+        // Pages are laid out by a template if there is one,
+        // or else by the built-in layout
+        let input = self.metadata.get_input();
+        let dir = input.parent()
+            .and_then(|p| p.strip_prefix(self.metadata.get_root()).ok())
+            .unwrap_or(Path::new(""));
+        let templates = self.metadata.get_templates();
+        let layout = templates.get_layout(dir, meta.template.as_deref())
+            .map_err(Error::CannotRenderTemplate)?;
+
+        let output = match layout {
+            Some (l) => l.render(&self.context(l, meta, &content), templates)
+                .map_err(Error::CannotRenderTemplate)?,
+            None => self.layout(meta, &content),
+        };
+
+        Ok (output.into_bytes())
+    }
+
+    /// Replaces the menu, date and footnotes within an expression by their
+    /// HTML, numbering the footnotes and collecting their contents in order.
+    fn fill_in(&self, expr: &mut Expression, meta: &PageMeta, footnotes: &mut Vec<Vec<Expression>>) {
+        match expr {
+            Expression::Menu => *expr = Self::block(self.metadata.get_menu().unwrap_or_default()),
+
+            // Finding the date may run git, so only do so if it is shown
+            Expression::Date => *expr = Self::block(
+                format!("<h6 class=\"last-updated-date\">Last Updated {}</h6>", self.last_updated(meta)),
+            ),
+            Expression::Footnote (v) => {
+                let mut contents = std::mem::take(v);
+                for e in &mut contents {
+                    self.fill_in(e, meta, footnotes);
+                }
+                footnotes.push(contents);
+                *expr = Expression::Raw (format!(
+                    "<a id=\"citation-{}\" href=\"#footnote-{}\"><sup>{}</sup></a>",
+                    footnotes.len(),
+                    footnotes.len(),
+                    footnotes.len(),
+                ));
+            },
+            _ => for child in expr.get_children_mut() {
+                self.fill_in(child, meta, footnotes);
+            },
+        }
+    }
 
-        output.push_str("<html>\n<head>");
+    /// Replaces the collections of footnotes within an expression by their HTML.
+    fn fill_in_footnotes(expr: &mut Expression, html: &str) {
+        match expr {
+            Expression::Footnotes => *expr = Self::block(html.to_string()),
+            _ => for child in expr.get_children_mut() {
+                Self::fill_in_footnotes(child, html);
+            },
+        }
+    }
+
+    /// Renders the collection of a page's footnotes.
+    fn footnotes(&self, footnotes: &[Vec<Expression>]) -> Result<String, Error> {
+        let mut footnote_text = "<h3>Footnotes</h3>\n".to_string();
+        for (index, footnote) in footnotes.iter().enumerate() {
+            footnote_text.push_str(
+                &format!(
+                    "<p>[<a id=\"footnote-{}\" href=\"#citation-{}\">{}</a>] {}</p>",
+                    index + 1,
+                    index + 1,
+                    index + 1,
+                    Expression::render_all_with(footnote, &self.renderers)?,
+                ),
+            );
+        }
+
+        Ok (footnote_text)
+    }
+
+    /// Wraps HTML so that it renders as it is, as a block element.
+    fn block(html: String) -> Expression {
+        Expression::Group (vec![Expression::Raw (html)])
+    }
+
+    /// Gets the date shown on the last page emitted, if it was found from
+    /// the page's files, such as its last commit, rather than its front matter.
+    pub fn get_found_date(&self) -> Option<NaiveDate> {
+        self.found_date.get()
+    }
+
+    /// Gets the formatted date on which a page was last updated.
+    fn last_updated(&self, meta: &PageMeta) -> String {
+        let explicit = meta.updated.or(meta.date);
+        let date = date::last_updated(
+            &self.metadata.get_input(),
+            explicit,
+            self.metadata.get_source_date_epoch(),
+        );
+        if explicit.is_none() {
+            self.found_date.set(Some (date));
+        }
+        date::format(date)
+    }
+
+    /// Assembles the contents of the `<head>` element, other than the
+    /// character set and viewport, which every page must declare first.
+    fn head(&self, meta: &PageMeta) -> String {
+        let mut output = String::new();
 
         if let Some (t) = self.metadata.get_analytics() {
             output.push_str(&t);
@@ -36,74 +207,313 @@ impl Emitter {
             output.push_str("\n<link rel=\"icon\" type=\"image/x-icon\" href=\"/media/favicon.ico\">\n");
         }
 
+        let pagename = meta.get_title();
         let full_pagename = if let Some (st) = self.metadata.get_sitename() {
-            format!("<title>{} | {}</title>", pagename, st)
+            format!("<title>{} | {}</title>", escape::text(&pagename), escape::text(&st))
         } else {
-            format!("<title>{}</title>", pagename)
+            format!("<title>{}</title>", escape::text(&pagename))
         };
         output.push_str(&full_pagename);
 
-        output.push_str("</head>\n");
+        if let Some (d) = &meta.description {
+            output.push_str(&format!("\n<meta name=\"description\" content=\"{}\">", escape::attribute(d)));
+        }
 
-        output.push_str("\n<body>\n");
+        if let Some (a) = &meta.author {
+            output.push_str(&format!("\n<meta name=\"author\" content=\"{}\">", escape::attribute(a)));
+        }
 
-        for expr in expressions {
-            let output_str = format!("{}\n", expr);
-            output.push_str(&output_str);
+        if !meta.tags.is_empty() {
+            output.push_str(&format!("\n<meta name=\"keywords\" content=\"{}\">", escape::attribute(&meta.tags.join(", "))));
         }
 
+        output
+    }
+
+    /// Lays out a page without a template.
+    fn layout(&self, meta: &PageMeta, content: &str) -> String {
+        let mut output = String::new();
+
+        output.push_str("<!DOCTYPE html>\n");
+        output.push_str(&format!("<html lang=\"{}\">\n<head>\n", escape::attribute(&self.metadata.get_lang())));
+        output.push_str("<meta charset=\"utf-8\">\n");
+        output.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+        output.push_str(&self.head(meta));
+        output.push_str("</head>\n");
+
+        output.push_str("\n<body>\n");
+        output.push_str(content);
         output.push_str("\n</body>\n");
 
         output.push_str("</html>");
 
-        if let Some (m) = self.metadata.get_menu() {
-            output = str::replace(&output, "<menu>", &m);
-        }
-
-        // Assemble footnotes
-        let mut footnote_number = 1;
-        let mut new_output = String::new();
-        let mut footnotes = Vec::<String>::new();
-        for footnote in output.split("<footnote>") {
-            let end_index = footnote.find("</footnote>").unwrap_or(footnote.len());
-            if end_index < footnote.len() {
-                let content = &footnote[0..end_index];
-                let rest = &footnote[end_index+11..];
-                new_output.push_str(
-                    &format!(
-                        "<a id=\"citation-{}\" href=\"#footnote-{}\"><sup>{}</sup></a>",
-                        footnote_number,
-                        footnote_number,
-                        footnote_number,
-                    ),
-                );
-                footnote_number += 1;
-                new_output.push_str(&rest);
-                footnotes.push(content.to_string());
-            } else {
-                new_output.push_str(&footnote);
-            }
-        }
-        output = new_output;
+        output
+    }
 
-        let mut footnote_text = "<h3>Footnotes</h3>\n".to_string();
-        for (index, footnote) in footnotes.iter().enumerate() {
-            footnote_text.push_str(
-                &format!(
-                    "<p>[<a id=\"footnote-{}\" href=\"#citation-{}\">{}</a>] {}</p>",
-                    index + 1,
-                    index + 1,
-                    index + 1,
-                    footnote,
-                ),
-            );
-        }
+    /// Gets the variables available to a page's template.
+    fn context(&self, layout: &Template, meta: &PageMeta, content: &str) -> Context {
+        let text = |s: &Option<String>| Value::Text (s.to_owned().unwrap_or_default());
+
+        // Finding the date may run git, so only do so if the layout shows it
+        let date = if layout.uses_with("date", self.metadata.get_templates()) {
+            self.last_updated(meta)
+        } else {
+            String::new()
+        };
 
-        output = str::replace(&output, "<footnotes>", &footnote_text);
+        Context::from([
+            ("lang".to_string(), Value::Text (self.metadata.get_lang())),
+            ("title".to_string(), Value::Text (meta.get_title())),
+            ("sitename".to_string(), text(&self.metadata.get_sitename())),
+            ("head".to_string(), Value::Html (self.head(meta))),
+            ("content".to_string(), Value::Html (content.to_string())),
+            ("menu".to_string(), Value::Html (self.metadata.get_menu().unwrap_or_default())),
+            ("date".to_string(), Value::Text (date)),
+            ("published".to_string(), Value::Text (meta.date.map(date::format).unwrap_or_default())),
+            ("description".to_string(), text(&meta.description)),
+            ("author".to_string(), text(&meta.author)),
+            ("tags".to_string(), Value::List (meta.tags.iter().map(|t| Value::Text (t.to_owned())).collect())),
+            ("draft".to_string(), Value::Boolean (meta.draft)),
+            ("template".to_string(), text(&meta.template)),
+            ("slug".to_string(), text(&meta.slug)),
+        ])
+    }
+}
+
+#[test]
+fn multilingual_text() {
+    let text = "Café — “quoted” naïve façade, Ελληνικά, 日本語, עברית, 🎉";
+    let expressions = crate::Parser::new()
+        .parse(&format!("\\pagename{{Überblick}}\n# {}\n~ {}\\footnote{{Ünïcødé → ✓}}\n\\footnotes", text, text))
+        .unwrap();
+    let emitted = Emitter::new(&Metadata::default())
+        .emit(expressions, &PageMeta {
+            title: Some ("Überblick".to_string()),
+            ..PageMeta::default()
+        })
+        .unwrap();
+    let output = String::from_utf8(emitted).unwrap();
+
+    assert!(output.contains("<meta charset=\"utf-8\">"));
+    assert!(output.contains("<title>Überblick</title>"));
+    assert!(output.contains(&format!("<h2>{}</h2>", text)));
+    assert!(output.contains(&format!("<p>{}<a id=\"citation-1\"", text)));
+    assert!(output.contains("</a>] Ünïcødé → ✓</p>"));
+}
+
+/// Emits a full document containing the given expressions, and checks
+/// that an HTML5 parser accepts it without errors.
+#[cfg(test)]
+fn assert_well_formed(expressions: Vec<Expression>) {
+    use html5ever::{
+        parse_document,
+        tendril::TendrilSink,
+    };
+    use markup5ever_rcdom::RcDom;
+
+    let emitted = Emitter::new(&Metadata::default())
+        .emit(expressions.clone(), &PageMeta::default())
+        .unwrap();
+    let output = String::from_utf8(emitted).unwrap();
+
+    let dom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut output.as_bytes())
+        .unwrap();
+    assert!(dom.errors.is_empty(), "{:?} in {:?}:\n{}", dom.errors, expressions, output);
+
+    // HTML5 parsers implicitly close paragraphs, so check that explicitly
+    assert_eq!(output.matches("<p>").count() + output.matches("<p ").count(), output.matches("</p>").count());
+}
+
+#[test]
+fn well_formed_expressions() {
+    use crate::Alignment;
+    use Expression::*;
+
+    let text = |s: &str| vec![Alphanumeric (s.to_string())];
+    let dir = crate::test_dir::TestDir::new("well-formed");
+    let code = dir.write("main.rs", "fn main() { let x = 1 < 2; }");
+    let html = dir.write("form.html", "<form><input type=\"text\"></form>");
+    let csv = dir.write("data.csv", "Item,Cost\nTea,3\n\"A, B\",4\n");
+
+    // Tables read from CSV files are parsed, to check their alignments and caption
+    let mut table = crate::Parser::new()
+        .parse(&format!("\\table{{{}}}{{lr}}\n\\caption{{Costs \\bold{{now}}}}", csv.display()))
+        .unwrap();
+    assert!(matches!(
+        &table[..],
+        [Table { file: Some (_), alignments, caption, .. }] if alignments.len() == 2 && !caption.is_empty()
+    ));
+
+    let mut expressions = vec![
+        Title (text("Title")),
+        Heading (text("Heading")),
+        Subheading (text("Subheading")),
+        Subtitle (text("Subtitle")),
+        Subsubtitle (text("Subsubtitle")),
+        Paragraph (text("Paragraph")),
+        Alphanumeric ("Text".to_string()),
+        Newline,
+        Bold (text("Bold")),
+        Italic (text("Italic")),
+        BlockQuote {
+            quote: text("Quote"),
+            citation: text("Citation"),
+        },
+        Hyperlink {
+            name: text("Link"),
+            href: "/page.html".to_string(),
+        },
+        Code {
+            language: "rust".to_string(),
+            code: code.display().to_string(),
+        },
+        Tile {
+            name: text("Tile"),
+            img: "/tile.png".to_string(),
+            href: "/page.html".to_string(),
+        },
+        TileDesc {
+            name: text("Tile"),
+            desc: text("Description"),
+            img: "/tile.png".to_string(),
+            href: "/page.html".to_string(),
+        },
+        Image {
+            img: "/image.png".to_string(),
+            alt: "Image".to_string(),
+            scale: "100".to_string(),
+        },
+        FloatingImage {
+            img: "/image.png".to_string(),
+            alt: "Image".to_string(),
+        },
+        Header (text("Header")),
+        Footer (text("Footer")),
+        Footnote (text("Footnote")),
+        Footnotes,
+        Topblock (text("Topblock")),
+        Menu,
+        Date,
+        Tiles (vec![
+            Tile {
+                name: text("Tile"),
+                img: "/tile.png".to_string(),
+                href: "/page.html".to_string(),
+            },
+        ]),
+        Pagename ("Page".to_string()),
+        Raw ("<span>Raw</span>".to_string()),
+        HtmlFile (html.display().to_string()),
+        UnorderedList (vec![text("One"), text("Two")]),
+        OrderedList {
+            start: 1,
+            items: vec![text("One")],
+        },
+        OrderedList {
+            start: 3,
+            items: vec![text("Three"), text("Four")],
+        },
+        UnorderedList (vec![
+            vec![
+                Alphanumeric ("Outer".to_string()),
+                OrderedList {
+                    start: 2,
+                    items: vec![text("Inner"), vec![UnorderedList (vec![text("Deepest")])]],
+                },
+            ],
+            text("Last"),
+        ]),
+        Table {
+            alignments: vec![Alignment::Left, Alignment::Center, Alignment::Right],
+            header: vec![vec![text("A"), text("B"), text("C")]],
+            body: vec![vec![text("1"), Vec::new(), vec![Bold (text("3"))]]],
+            caption: text("Caption"),
+            file: None,
+        },
+    ];
+    expressions.append(&mut table);
 
-        
-        output.chars()
-            .map(|c| c as u8)
-            .collect::<Vec<u8>>()
+    let numbered = OrderedList {
+        start: 3,
+        items: vec![text("Three")],
+    };
+    assert_eq!(numbered.render().unwrap(), "<ol start=\"3\">\n<li>Three</li>\n</ol>");
+
+    for expr in &expressions {
+        assert_well_formed(vec![expr.to_owned()]);
+        assert_well_formed(vec![Paragraph (vec![Alphanumeric ("Before ".to_string()), expr.to_owned()])]);
     }
-}
\ No newline at end of file
+    assert_well_formed(expressions);
+}
+
+#[test]
+fn escaped_expressions() {
+    use Expression::*;
+
+    let hostile = "\"><script>alert('x & y')</script>";
+    let expressions = vec![
+        Paragraph (vec![Alphanumeric (hostile.to_string())]),
+        Hyperlink {
+            name: vec![Alphanumeric (hostile.to_string())],
+            href: hostile.to_string(),
+        },
+        Hyperlink {
+            name: vec![Alphanumeric ("Link".to_string())],
+            href: "javascript:alert(1)".to_string(),
+        },
+        Image {
+            img: hostile.to_string(),
+            alt: hostile.to_string(),
+            scale: hostile.to_string(),
+        },
+        Tile {
+            name: vec![Alphanumeric (hostile.to_string())],
+            img: hostile.to_string(),
+            href: hostile.to_string(),
+        },
+    ];
+
+    let emitted = Emitter::new(&Metadata::default())
+        .emit(expressions.clone(), &PageMeta {
+            title: Some (hostile.to_string()),
+            description: Some (hostile.to_string()),
+            ..PageMeta::default()
+        })
+        .unwrap();
+    let output = String::from_utf8(emitted).unwrap();
+
+    assert!(!output.contains("<script>"));
+    assert!(!output.contains("javascript:"));
+    assert!(output.contains("<a href=\"#\">Link</a>"));
+    assert_well_formed(expressions);
+}
+
+#[test]
+fn well_formed_page() {
+    let source = "\\pagename{Home}\n\\header{Site}\n\\menu\n# Hello, \\href{world}{/}!\n~ Some \\bold{bold} text.\n## Sub\n~ A note\\footnote{here}.\n\\tiles{\\tile{One}{/a.png}{/a.html}}\n\\footnotes\n\\date\n";
+    assert_well_formed(crate::Parser::new().parse(source).unwrap());
+}
+
+#[test]
+fn template_placeholders() {
+    let site = crate::test_dir::TestDir::new("template-placeholders");
+    site.write("blog.toml", "menu = \"menu.html\"\n");
+    site.write("menu.html", "<a href=\"/\">Home</a>");
+    site.write("templates/default.html", "<menu><li>Tools</li></menu>{{ content }}");
+    let source = "\\menu\n~ Hi \\raw{<menu><li>x</li></menu>}\\footnote{Note}\n\\footnotes";
+    let page = site.write("a.txt", source);
+
+    let args = ["compile".to_string(), page.display().to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+    let expressions = crate::Parser::new().parse(source).unwrap();
+    let emitted = Emitter::new(&metadata).emit(expressions, &PageMeta::default()).unwrap();
+    let output = String::from_utf8(emitted).unwrap();
+
+    // Only the page's menu is filled in, not the markup of the layout or raw HTML
+    assert!(output.starts_with("<menu><li>Tools</li></menu><a href=\"/\">Home</a>"), "{}", output);
+    assert!(output.contains("<menu><li>x</li></menu><a id=\"citation-1\""), "{}", output);
+    assert_eq!(output.matches("Home").count(), 1);
+    assert!(output.contains("] Note</p>"), "{}", output);
+}
@@ -0,0 +1,109 @@
+//! Context-aware HTML escaping for the Blog Builder emitter module.
+
+/// URL schemes that may be used in links and image sources.
+const ALLOWED_SCHEMES: [&str; 5] = ["http", "https", "mailto", "tel", "ftp"];
+
+/// Escapes text for use as HTML element content.
+pub fn text(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// Escapes text for use within a double-quoted HTML attribute value.
+pub fn attribute(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// Replaces URLs with schemes other than those in `ALLOWED_SCHEMES`,
+/// such as `javascript:`, with `#`.
+fn sanitize_url(input: &str) -> String {
+    let input = input.trim();
+
+    // A scheme is everything before the first colon, as long as
+    // the colon comes before any path, query or fragment
+    let scheme = input.find(':')
+        .filter(|&i| !input[..i].contains(['/', '?', '#']))
+        .map(|i| input[..i].to_ascii_lowercase());
+
+    match scheme {
+        Some (s) if !ALLOWED_SCHEMES.contains(&s.as_str()) => "#".to_string(),
+        _ => input.to_string(),
+    }
+}
+
+/// Sanitizes a URL for use within a double-quoted HTML attribute value.
+pub fn url(input: &str) -> String {
+    attribute(&sanitize_url(input))
+}
+
+/// Sanitizes a URL for use within a single-quoted JavaScript string
+/// inside a double-quoted HTML attribute value.
+pub fn script_url(input: &str) -> String {
+    let mut output = String::new();
+    for c in sanitize_url(input).chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            '\'' => output.push_str("\\'"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            _ => output.push(c),
+        }
+    }
+    attribute(&output)
+}
+
+/// Sanitizes a URL for use within a single-quoted CSS `url('...')`
+/// inside a double-quoted HTML attribute value.
+pub fn style_url(input: &str) -> String {
+    let mut output = String::new();
+    for c in sanitize_url(input).chars() {
+        match c {
+            '\\' => output.push_str("%5C"),
+            '\'' => output.push_str("%27"),
+            '"' => output.push_str("%22"),
+            '(' => output.push_str("%28"),
+            ')' => output.push_str("%29"),
+            c if c.is_whitespace() => output.push_str("%20"),
+            _ => output.push(c),
+        }
+    }
+    attribute(&output)
+}
+
+/// Sanitizes a CSS length, keeping only digits and decimal points.
+pub fn number(input: &str) -> String {
+    input.chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.')
+        .collect()
+}
+
+#[test]
+fn escape_contexts() {
+    assert_eq!(text("Tom & Jerry <script>"), "Tom &amp; Jerry &lt;script&gt;");
+    assert_eq!(attribute("a \"quoted\" 'alt'"), "a &quot;quoted&quot; &#39;alt&#39;");
+    assert_eq!(url("https://example.com/?a=1&b=2"), "https://example.com/?a=1&amp;b=2");
+    assert_eq!(url("/page.html#section:2"), "/page.html#section:2");
+    assert_eq!(url(" JavaScript:alert(1)"), "#");
+    assert_eq!(script_url("/a'b\\c"), "/a\\&#39;b\\\\c");
+    assert_eq!(style_url("/my image (1).png"), "/my%20image%20%281%29.png");
+    assert_eq!(number("100px\"; onload=\"x"), "100");
+}
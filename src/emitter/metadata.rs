@@ -5,134 +5,539 @@ use std::{
     fs,
     io::{Read, Write},
     path::{Path, PathBuf},
+    thread,
 };
 
+use walkdir::WalkDir;
+
 use crate::Error;
 
+use super::{
+    Config,
+    Templates,
+    Theme,
+    CONFIG_FILENAME,
+    SOURCE_DATE_EPOCH,
+};
+
+/// Flags accepted by every command, and whether each flag takes a value.
+const FLAGS: [(&str, bool); 7] = [
+    ("--style", true),
+    ("--sitename", true),
+    ("--menu", true),
+    ("--icon", false),
+    ("--analytics", true),
+    ("--lang", true),
+    ("--out", true),
+];
+
+/// Flags accepted by the commands that build a whole directory.
+const BUILD_FLAGS: [(&str, bool); 2] = [
+    ("--force", false),
+    ("--jobs", true),
+];
+
+/// Flags accepted only by the `serve` command.
+const SERVE_FLAGS: [(&str, bool); 1] = [
+    ("--port", true),
+];
+
+/// Default directory containing layout templates, within the site root.
+const TEMPLATES_DIR: &str = "templates";
+
+/// Default directory containing partial pages, within the site root.
+const PARTIALS_DIR: &str = "partials";
+
+/// Default port for the development server.
+const DEFAULT_PORT: u16 = 8000;
+
 #[derive(Clone, Debug)]
 pub struct Metadata {
+    args: Vec<String>,
     command_option: CommandOption,
+    help: bool,
     input: PathBuf,
+    root: PathBuf,
+    style: Option<String>,
+    css: Option<String>,
     stylesheet: Option<String>,
     links: Option<String>,
     sitename: Option<String>,
     menu: Option<String>,
     icon: bool,
     analytics: Option<String>,
+    lang: Option<String>,
+    out: Option<PathBuf>,
+    port: u16,
+    force: bool,
+    jobs: Option<usize>,
+    source_date_epoch: Option<i64>,
+    theme: Option<Theme>,
+    template_dir: Option<PathBuf>,
+    templates: Templates,
+    partials_dir: Option<PathBuf>,
+    macros: Option<(PathBuf, String)>,
+    shared_inputs: Vec<PathBuf>,
+    config: Config,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CommandOption {
     Compile,
     Build,
+    Serve,
+    Watch,
     Help,
 }
 
-impl Metadata {
-    pub fn get() -> Self {
-        let args = env::args().collect::<Vec<String>>();
+impl CommandOption {
+    /// Gets the command with the given name, or returns an error.
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "compile" => Ok (Self::Compile),
+            "build" => Ok (Self::Build),
+            "serve" => Ok (Self::Serve),
+            "watch" => Ok (Self::Watch),
+            "help" => Ok (Self::Help),
+            _ => Err (Error::UnknownCommand (name.to_string())),
+        }
+    }
+
+    /// Gets the name of this command.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Compile => "compile",
+            Self::Build => "build",
+            Self::Serve => "serve",
+            Self::Watch => "watch",
+            Self::Help => "help",
+        }
+    }
 
-        if args.len() < 3 {
-            return Self::default();
+    /// Gets the flags accepted by this command,
+    /// and whether each flag takes a value.
+    pub fn get_flags(&self) -> Vec<(&'static str, bool)> {
+        match self {
+            Self::Serve => FLAGS.iter()
+                .chain(BUILD_FLAGS.iter())
+                .chain(SERVE_FLAGS.iter())
+                .copied()
+                .collect(),
+            Self::Build | Self::Watch => FLAGS.iter().chain(BUILD_FLAGS.iter()).copied().collect(),
+            _ => FLAGS.to_vec(),
         }
+    }
+}
 
-        let command_option = match args[1].as_str() {
-            "compile" => CommandOption::Compile,
-            "build" => CommandOption::Build,
-            _ => CommandOption::Help,
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            args: Vec::new(),
+            command_option: CommandOption::Help,
+            help: true,
+            input: PathBuf::new(),
+            root: PathBuf::new(),
+            style: None,
+            css: None,
+            stylesheet: None,
+            links: None,
+            sitename: None,
+            menu: None,
+            icon: false,
+            analytics: None,
+            lang: None,
+            out: None,
+            port: DEFAULT_PORT,
+            force: false,
+            jobs: None,
+            source_date_epoch: None,
+            theme: None,
+            template_dir: None,
+            templates: Templates::default(),
+            partials_dir: None,
+            macros: None,
+            shared_inputs: Vec::new(),
+            config: Config::default(),
+        }
+    }
+}
+
+impl Metadata {
+    pub fn get() -> Result<Self, Error> {
+        let args = env::args().skip(1).collect::<Vec<String>>();
+        Self::from_args(&args)
+    }
+
+    /// Constructs metadata from command-line arguments, not including
+    /// the name of the executable.
+    /// 
+    /// Arguments take the form `<command> [flags] <input>`, where flags
+    /// may come before or after the input.
+    pub fn from_args(args: &[String]) -> Result<Self, Error> {
+        let command_option = match args.first().map(|a| a.as_str()) {
+            None | Some ("help") | Some ("--help") | Some ("-h") => {
+                let topic = match args.get(1).map(|a| a.as_str()) {
+                    None => CommandOption::Help,
+                    Some (a) => CommandOption::from_name(a)?,
+                };
+                return Ok (Self {
+                    command_option: topic,
+                    help: true,
+                    ..Self::default()
+                });
+            },
+            Some (a) => CommandOption::from_name(a)?,
         };
-        let input = PathBuf::from(args[2].to_owned());
 
-        let mut stylesheet = None;
-        let mut links = None;
-        let mut sitename = None;
-        let mut menu = None;
-        let mut icon = false; 
-        let mut analytics = None;
+        let mut input = None;
+        let mut flags = Vec::new();
 
-        let mut i = 3;
+        let mut i = 1;
         while i < args.len() {
-            match args[i].as_str() {
-                "--style" => {
-                    Self::set_style(
-                        &args[i + 1],
-                        &input,
-                        &mut stylesheet,
-                        &mut links,
-                    );
-                    i += 2;
-                },
-                "--sitename" => {
-                    sitename = Some (args[i + 1].to_string());
-                    i += 2;
-                },
-                "--menu" => {
-                    // Opens the file provided and reads its contents
-                    let filename = &args[i + 1];
-                    let mut file = match fs::OpenOptions::new()
-                        .read(true)
-                        .open(filename)
-                    {
-                        Ok (f) => f,
-                        Err (_) => Error::CannotFindFile.throw_msg(filename),
-                    };
-                    let mut contents = String::new();
-                    match file.read_to_string(&mut contents) {
-                        Ok (_) => (),
-                        Err (_) => Error::CannotReadFile.throw(),
-                    }
-
-                    menu = Some (contents);
+            let arg = args[i].as_str();
+            if arg == "--help" || arg == "-h" {
+                return Ok (Self {
+                    command_option,
+                    help: true,
+                    ..Self::default()
+                });
+            } else if arg.starts_with("--") {
+                let takes_value = match command_option.get_flags().iter().find(|(f, _)| *f == arg) {
+                    Some ((_, v)) => *v,
+                    None => return Err (Error::UnknownFlag {
+                        flag: arg.to_string(),
+                        command: command_option.get_name().to_string(),
+                    }),
+                };
 
+                if takes_value {
+                    match args.get(i + 1) {
+                        Some (v) if !v.starts_with("--") => flags.push((arg, Some (v.as_str()))),
+                        _ => return Err (Error::MissingValue (arg.to_string())),
+                    }
                     i += 2;
-                },
-                "--icon" => {
-                    icon = true;
+                } else {
+                    flags.push((arg, None));
                     i += 1;
-                },
-                "--analytics" => {
-                    // Opens the file provided and reads its contents
-                    let filename = &args[i + 1];
-                    let mut file = match fs::OpenOptions::new()
-                        .read(true)
-                        .open(filename)
-                    {
-                        Ok (f) => f,
-                        Err (_) => Error::CannotFindFile.throw_msg(filename),
-                    };
-                    let mut contents = String::new();
-                    match file.read_to_string(&mut contents) {
-                        Ok (_) => (),
-                        Err (_) => Error::CannotReadFile.throw(),
-                    }
+                }
+            } else if input.is_none() {
+                input = Some (PathBuf::from(arg));
+                i += 1;
+            } else {
+                return Err (Error::UnexpectedArgument (arg.to_string()));
+            }
+        }
 
-                    analytics = Some (contents);
+        let input = match input {
+            Some (i) => i,
+            None => return Err (Error::MissingInput (command_option.get_name().to_string())),
+        };
 
-                    i += 2;
+        // Command-line flags override the configuration file
+        let mut config = Config::discover(&input)?.unwrap_or_default();
+        let mut port = DEFAULT_PORT;
+        let mut force = false;
+        let mut jobs = None;
+
+        for (flag, value) in flags {
+            let value = value.unwrap_or_default();
+            match flag {
+                "--port" => port = match value.parse() {
+                    Ok (p) => p,
+                    Err (_) => return Err (Error::InvalidValue {
+                        flag: flag.to_string(),
+                        value: value.to_string(),
+                    }),
                 },
-                _ => return Self::default(),
+                "--jobs" => jobs = match value.parse() {
+                    Ok (0) | Err (_) => return Err (Error::InvalidValue {
+                        flag: flag.to_string(),
+                        value: value.to_string(),
+                    }),
+                    Ok (j) => Some (j),
+                },
+                "--force" => force = true,
+                "--style" => config.style = Some (value.to_string()),
+                "--sitename" => config.sitename = Some (value.to_string()),
+                "--menu" => config.menu = Some (PathBuf::from(value)),
+                "--icon" => config.icon = Some (true),
+                "--analytics" => config.analytics = Some (PathBuf::from(value)),
+                "--lang" => config.lang = Some (value.to_string()),
+                "--out" => config.out = Some (PathBuf::from(value)),
+                _ => unreachable!(),
             }
         }
 
-        Self {
+        let source_date_epoch = match env::var(SOURCE_DATE_EPOCH) {
+            Ok (v) => match v.trim().parse() {
+                Ok (s) => Some (s),
+                Err (_) => return Err (Error::InvalidEnvironmentVariable {
+                    name: SOURCE_DATE_EPOCH.to_string(),
+                    value: v,
+                }),
+            },
+            Err (_) => None,
+        };
+
+        Ok (Self {
+            args: args.to_vec(),
+            port,
+            force,
+            jobs,
+            source_date_epoch,
+            ..Self::from_config(command_option, input, config)?
+        })
+    }
+
+    /// Reconstructs this metadata from the same command-line arguments,
+    /// picking up changes to the configuration file and the files it refers to.
+    ///
+    /// Metadata constructed by `from_config` is reconstructed from the same
+    /// configuration instead, picking up changes to the files it refers to.
+    pub fn reload(&self) -> Result<Self, Error> {
+        if !self.args.is_empty() {
+            return Self::from_args(&self.args);
+        }
+
+        Ok (Self {
+            port: self.port,
+            force: self.force,
+            jobs: self.jobs,
+            source_date_epoch: self.source_date_epoch,
+            ..Self::from_config(self.command_option, self.input.to_owned(), self.config.to_owned())?
+        })
+    }
+
+    /// Constructs metadata from a site configuration,
+    /// reading the files that it refers to.
+    pub fn from_config(command_option: CommandOption, input: PathBuf, config: Config) -> Result<Self, Error> {
+        // The site root holds the configuration file, or is the input
+        // directory if there is no configuration file
+        let root = match &config.root {
+            Some (r) => r.to_owned(),
+            None if input.is_dir() => input.to_owned(),
+            None => input.parent().unwrap_or(Path::new("")).to_path_buf(),
+        };
+
+        // The configuration file may be found above an input such as `.`,
+        // so express the input relative to the site root, as `../posts`
+        let input = match &config.root {
+            Some (r) if input.strip_prefix(r).is_err() => match (input.canonicalize(), r.canonicalize()) {
+                (Ok (i), Ok (c)) => match i.strip_prefix(c) {
+                    Ok (relative) => r.join(relative),
+                    Err (_) => input,
+                },
+                _ => input,
+            },
+            _ => input,
+        };
+
+        let mut css = None;
+        let mut stylesheet = None;
+        let mut links = None;
+        let theme = match &config.style {
+            Some (style) => Self::set_style(
+                style,
+                &root,
+                &mut css,
+                &mut stylesheet,
+                &mut links,
+            )?,
+            None => None,
+        };
+
+        let menu = match &config.menu {
+            Some (m) => Some (Self::read_file(m)?),
+            None => None,
+        };
+
+        let analytics = match &config.analytics {
+            Some (a) => Some (Self::read_file(a)?),
+            None => None,
+        };
+
+        let macros = match &config.macros {
+            Some (m) => Some ((m.to_owned(), Self::read_file(m)?)),
+            None => None,
+        };
+
+        // Templates are optional, so the default directory may not exist
+        let template_dir = match &config.templates {
+            Some (t) => Some (t.to_owned()),
+            None => Some (root.join(TEMPLATES_DIR)).filter(|t| t.is_dir()),
+        };
+
+        // The site's templates take precedence over the theme's
+        let template_dirs = template_dir.iter()
+            .cloned()
+            .chain(theme.as_ref().and_then(|t| t.get_template_dir()))
+            .collect::<Vec<PathBuf>>();
+        let templates = Templates::load(&template_dirs)?;
+
+        // Partial pages are also optional
+        let partials_dir = match &config.partials {
+            Some (p) => Some (p.to_owned()),
+            None => Some (root.join(PARTIALS_DIR)).filter(|p| p.is_dir()),
+        };
+
+        // Every page depends on the configuration file and the files it refers to
+        let theme_files = theme.iter()
+            .flat_map(|t| WalkDir::new(t.get_dir()))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().to_path_buf());
+        let shared_inputs = config.root.as_ref()
+            .map(|r| r.join(CONFIG_FILENAME))
+            .into_iter()
+            .chain(config.menu.iter().cloned())
+            .chain(config.analytics.iter().cloned())
+            .chain(config.macros.iter().cloned())
+            .chain(theme_files)
+            .collect();
+
+        Ok (Self {
+            args: Vec::new(),
             command_option,
+            help: false,
             input,
+            root,
+            style: config.style.to_owned(),
+            css,
             stylesheet,
             links,
-            sitename,
+            sitename: config.sitename.to_owned(),
             menu,
-            icon,
+            icon: config.icon.unwrap_or(false),
             analytics,
+            lang: config.lang.to_owned(),
+            out: config.out.to_owned(),
+            port: DEFAULT_PORT,
+            force: false,
+            jobs: None,
+            source_date_epoch: None,
+            theme,
+            template_dir,
+            templates,
+            partials_dir,
+            macros,
+            shared_inputs,
+            config,
+        })
+    }
+
+    /// Opens the file provided and reads its contents.
+    fn read_file(path: &Path) -> Result<String, Error> {
+        let path = path.to_path_buf();
+        let mut file = match fs::OpenOptions::new()
+            .read(true)
+            .open(&path)
+        {
+            Ok (f) => f,
+            Err (e) => return Err (Error::CannotFindFile {
+                path,
+                source: e,
+            }),
+        };
+        let mut contents = String::new();
+        match file.read_to_string(&mut contents) {
+            Ok (_) => Ok (contents),
+            Err (e) => Err (Error::CannotReadFile {
+                path,
+                source: e,
+            }),
         }
     }
 
+    /// Sets the stylesheet of a built-in style or theme directory, which
+    /// is written by `prepare_output`, and the HTML that links to it.
+    ///
+    /// Themes are looked up relative to the working directory, then to
+    /// the site root.  Any required selectors that they do not implement
+    /// are reported.
     fn set_style(
         style: &str,
-        input: &Path,
+        root: &Path,
+        css: &mut Option<String>,
         stylesheet: &mut Option<String>,
         links: &mut Option<String>,
-    ) {
-        let stylesheet_path = &input.join("style.css");
+    ) -> Result<Option<Theme>, Error> {
+        let builtin = match style {
+            "citizen" => Some ((
+                include_str!("metadata/styles/citizen.css"),
+                include_str!("metadata/links/citizen.html"),
+            )),
+            "modern" => Some ((
+                include_str!("metadata/styles/modern.css"),
+                include_str!("metadata/links/modern.html"),
+            )),
+            "truth" => Some ((
+                include_str!("metadata/styles/truth.css"),
+                include_str!("metadata/links/truth.html"),
+            )),
+            "tech" => Some ((
+                include_str!("metadata/styles/tech.css"),
+                include_str!("metadata/links/tech.html"),
+            )),
+            _ => None,
+        };
+
+        let (stylesheet_css, theme) = match builtin {
+            Some ((css, html)) => {
+                *links = Some (html.to_string());
+                (css.to_string(), None)
+            },
+            None => {
+                let dir = [PathBuf::from(style), root.join(style)].into_iter()
+                    .find(|d| d.is_dir());
+                let theme = match dir {
+                    Some (d) => Theme::load(&d)?,
+                    None => return Err (Error::UnknownStyle (style.to_string())),
+                };
+
+                let missing = theme.get_missing_selectors();
+                if !missing.is_empty() {
+                    eprintln!("[WARNING] theme {} does not style these required selectors:", theme.get_dir().display());
+                    for (selector, control) in missing {
+                        eprintln!("    {:<12} used by {}", selector, control);
+                    }
+                }
+
+                *links = theme.get_links();
+                (theme.get_css(), Some (theme))
+            },
+        };
+
+        *css = Some (stylesheet_css);
+        *stylesheet = Some ("<link rel=\"stylesheet\" href=\"/style.css\">".to_string());
+
+        Ok (theme)
+    }
+
+    /// Creates the output directory, if there is one, and writes the
+    /// stylesheet and theme assets into it, or into the site root
+    /// without one.  Builds and compiles do this before writing pages.
+    pub fn prepare_output(&self) -> Result<(), Error> {
+        if let Some (out) = &self.out {
+            if let Err (e) = fs::create_dir_all(out) {
+                return Err (Error::CannotCreateDir {
+                    path: out.to_owned(),
+                    source: e,
+                });
+            }
+        }
+
+        let css = match &self.css {
+            Some (c) => c,
+            None => return Ok (()),
+        };
+
+        // Theme assets are copied alongside the stylesheet
+        let destination = self.out.as_ref().unwrap_or(&self.root);
+        if let Some (theme) = &self.theme {
+            theme.copy_assets(destination)?;
+        }
+
+        let stylesheet_path = destination.join("style.css");
         let mut output = match fs::OpenOptions::new()
             .create(true)
             .write(true)
@@ -140,50 +545,19 @@ impl Metadata {
             .open(&stylesheet_path)
         {
             Ok (f) => f,
-            Err (_) => Error::CannotOpenFile.throw_msg(&stylesheet_path.display()),
-        };
-        let stylesheet_css = match style {
-            "citizen" => include_str!("metadata/styles/citizen.css"),
-            "modern" => include_str!("metadata/styles/modern.css"),
-            "truth" => include_str!("metadata/styles/truth.css"),
-            "tech" => include_str!("metadata/styles/tech.css"),
-            _ => "",
+            Err (e) => return Err (Error::CannotOpenFile {
+                path: stylesheet_path,
+                source: e,
+            }),
         };
-        match output.write_all(stylesheet_css.as_bytes()) {
-            Ok (_) => (),
-            Err (_) => Error::CannotWriteFile.throw(),
-        };
-        
-        *stylesheet = Some ("<link rel=\"stylesheet\" href=\"/style.css\">".to_string());
-
-        match style {
-            "citizen" => {
-                *links = Some (include_str!("metadata/links/citizen.html").to_string());
-            },
-            "modern" => {
-                *links = Some (include_str!("metadata/links/modern.html").to_string());
-            },
-            "truth" => {
-                *links = Some (include_str!("metadata/links/truth.html").to_string());
-            },
-            "tech" => {
-                *links = Some (include_str!("metadata/links/tech.html").to_string());
-            },
-            _ => return,
+        if let Err (e) = output.write_all(css.as_bytes()) {
+            return Err (Error::CannotWriteFile {
+                path: stylesheet_path,
+                source: e,
+            });
         }
-    }
 
-    fn default() -> Self {
-        Self {
-            command_option: CommandOption::Help,
-            input: PathBuf::new(),
-            stylesheet: None,
-            links: None,
-            sitename: None,
-            menu: None,
-            icon: false,
-            analytics: None,
-        }
+        Ok (())
     }
 
     pub fn with_input(&self, input: PathBuf) -> Self {
@@ -193,6 +567,10 @@ impl Metadata {
         }
     }
 
+    pub fn get_style(&self) -> Option<String> {
+        self.style.to_owned()
+    }
+
     pub fn get_stylesheet(&self) -> Option<String> {
         self.stylesheet.to_owned()
     }
@@ -217,11 +595,133 @@ impl Metadata {
         self.analytics.to_owned()
     }
 
+    pub fn get_lang(&self) -> String {
+        self.lang.to_owned().unwrap_or("en".to_string())
+    }
+
     pub fn get_command_option(&self) -> CommandOption {
         self.command_option
     }
 
+    /// Checks whether help was requested for the command.
+    pub fn is_help(&self) -> bool {
+        self.help
+    }
+
     pub fn get_input(&self) -> PathBuf {
         self.input.to_owned()
     }
-}
\ No newline at end of file
+
+    pub fn get_root(&self) -> PathBuf {
+        self.root.to_owned()
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Gets the number of pages to compile at once, which defaults
+    /// to the number of available cores.
+    pub fn get_jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub fn get_theme(&self) -> Option<&Theme> {
+        self.theme.as_ref()
+    }
+
+    /// Gets the directory containing layout templates, if there is one.
+    pub fn get_template_dir(&self) -> Option<PathBuf> {
+        self.template_dir.to_owned()
+    }
+
+    /// Gets the directory containing partial pages, if there is one.
+    pub fn get_partials_dir(&self) -> Option<PathBuf> {
+        self.partials_dir.to_owned()
+    }
+
+    pub fn get_templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// Gets the path and contents of the site's macros file, if it has one.
+    pub fn get_macros(&self) -> Option<(PathBuf, String)> {
+        self.macros.to_owned()
+    }
+
+    /// Gets the date that overrides the dates of pages, in seconds
+    /// since the Unix epoch, if `SOURCE_DATE_EPOCH` is set.
+    pub fn get_source_date_epoch(&self) -> Option<i64> {
+        self.source_date_epoch
+    }
+
+    /// Checks whether every page should be compiled, ignoring the build cache.
+    pub fn is_force(&self) -> bool {
+        self.force
+    }
+
+    pub fn get_out(&self) -> Option<PathBuf> {
+        self.out.to_owned()
+    }
+
+    /// Gets the files read by every page: the configuration file, the
+    /// menu, the analytics file, the macros file and the theme's files.
+    pub fn get_shared_inputs(&self) -> Vec<PathBuf> {
+        self.shared_inputs.to_owned()
+    }
+
+    /// Gets the path at which the output for a source file is written.
+    /// 
+    /// With an output directory, the source tree is mirrored into it.
+    /// Otherwise, the output is written next to the source file.
+    pub fn get_output_path(&self, source: &Path) -> PathBuf {
+        match &self.out {
+            Some (out) => match source.strip_prefix(&self.root) {
+                Ok (relative) => out.join(relative),
+                Err (_) => out.join(source.file_name().unwrap_or_default()),
+            },
+            None => source.to_path_buf(),
+        }
+    }
+}
+
+#[test]
+fn parse_arguments() {
+    let args = |a: &str| a.split_whitespace()
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+
+    let metadata = Metadata::from_args(&args("build --sitename Site --icon .")).unwrap();
+    assert_eq!(metadata.get_command_option(), CommandOption::Build);
+    assert_eq!(metadata.get_input(), PathBuf::from("."));
+    assert_eq!(metadata.get_sitename().as_deref(), Some ("Site"));
+    assert!(metadata.is_icon());
+    assert!(!metadata.is_help());
+    assert_eq!(metadata.get_output_path(Path::new("posts/a.txt")), PathBuf::from("posts/a.txt"));
+
+    let out = crate::test_dir::TestDir::new("parse-arguments");
+    let metadata = Metadata::from_args(&args(&format!("compile src/lib.rs --out {}", out.path().display()))).unwrap();
+    assert_eq!(metadata.get_output_path(Path::new("src/parser/mod.rs")), out.join("parser/mod.rs"));
+
+    let metadata = Metadata::from_args(&args("help compile")).unwrap();
+    assert_eq!(metadata.get_command_option(), CommandOption::Compile);
+    assert!(metadata.is_help());
+
+    let metadata = Metadata::from_args(&args("build --help")).unwrap();
+    assert_eq!(metadata.get_command_option(), CommandOption::Build);
+    assert!(metadata.is_help());
+
+    assert!(matches!(Metadata::from_args(&args("biuld .")), Err (Error::UnknownCommand (_))));
+    assert!(matches!(Metadata::from_args(&args("build . --colour red")), Err (Error::UnknownFlag { .. })));
+    assert!(matches!(Metadata::from_args(&args("build . --style")), Err (Error::MissingValue (_))));
+    assert!(matches!(Metadata::from_args(&args("build . --style --icon")), Err (Error::MissingValue (_))));
+    assert!(matches!(Metadata::from_args(&args("build")), Err (Error::MissingInput (_))));
+    assert!(matches!(Metadata::from_args(&args("build . --port 80")), Err (Error::UnknownFlag { .. })));
+    assert!(matches!(Metadata::from_args(&args("serve . --port eighty")), Err (Error::InvalidValue { .. })));
+    assert_eq!(Metadata::from_args(&args("serve --port 8080 .")).unwrap().get_port(), 8080);
+    assert!(Metadata::from_args(&args("build . --force")).unwrap().is_force());
+    assert_eq!(Metadata::from_args(&args("build . --jobs 3")).unwrap().get_jobs(), 3);
+    assert!(matches!(Metadata::from_args(&args("build . --jobs 0")), Err (Error::InvalidValue { .. })));
+    assert!(matches!(Metadata::from_args(&args("compile src/lib.rs --force")), Err (Error::UnknownFlag { .. })));
+    assert!(matches!(Metadata::from_args(&args("build . extra")), Err (Error::UnexpectedArgument (_))));
+}
@@ -1,11 +1,49 @@
 //! HTML Emitter module for the Blog Builder.
 
+mod config;
+mod date;
+#[allow(clippy::module_inception)]
 mod emitter;
 mod metadata;
+mod renderer;
+mod theme;
+mod template;
+
+pub mod escape;
+
+pub use config::{
+    Config,
+    CONFIG_FILENAME,
+};
+
+pub(crate) use config::{
+    boolean,
+    string,
+    unknown_key,
+};
+
+pub use date::{
+    History,
+    SOURCE_DATE_EPOCH,
+};
 
 pub use emitter::Emitter;
 
 pub use metadata::{
     Metadata,
     CommandOption,
+};
+
+pub use renderer::{
+    Renderer,
+    Renderers,
+};
+
+pub use theme::Theme;
+
+pub use template::{
+    Context,
+    Template,
+    Templates,
+    Value,
 };
\ No newline at end of file
@@ -0,0 +1,73 @@
+//! Renderers for extensions to the Blog Builder.
+//!
+//! Each control sequence added to the parser with a `ControlHandler`
+//! produces an `Extension`, which the emitter renders with the renderer
+//! registered under the extension's name:
+//!
+//! ```text
+//! let emitter = Emitter::new(&metadata)
+//!     .renderer("youtube", YouTube);
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+use crate::{
+    parser::Extension,
+    Error,
+};
+
+/// Renderer for extensions of one name.
+pub trait Renderer: Send + Sync {
+    /// Renders an extension into HTML.  `children` holds the rendered
+    /// HTML of each of the extension's children, in order.
+    fn render(&self, extension: &Extension, children: &[String]) -> Result<String, Error>;
+
+    /// Identifies this renderer and the version of its output, as
+    /// `ControlHandler::version` does for handlers.
+    fn version(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+#[derive(Clone, Default)]
+/// Renderers registered for extensions, by name.
+pub struct Renderers {
+    renderers: HashMap<String, Arc<dyn Renderer>>,
+}
+
+impl Renderers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a renderer, replacing any earlier renderer of the same name.
+    pub fn insert(&mut self, name: &str, renderer: impl Renderer + 'static) {
+        self.renderers.insert(name.to_string(), Arc::new(renderer));
+    }
+
+    /// Registers every renderer of another set, replacing any
+    /// earlier renderers of the same names.
+    pub fn extend(&mut self, other: &Renderers) {
+        self.renderers.extend(other.renderers.iter().map(|(n, r)| (n.to_owned(), r.clone())));
+    }
+
+    /// Lists the name and version of every renderer, in order of name.
+    pub fn get_versions(&self) -> Vec<String> {
+        let mut versions = self.renderers.iter()
+            .map(|(n, r)| format!("renderer {} {}", n, r.version()))
+            .collect::<Vec<String>>();
+        versions.sort();
+        versions
+    }
+
+    /// Renders an extension, given the rendered HTML of its children.
+    pub fn render(&self, extension: &Extension, children: &[String]) -> Result<String, Error> {
+        match self.renderers.get(extension.get_name()) {
+            Some (r) => r.render(extension, children),
+            None => Err (Error::UnrenderedExtension (extension.get_name().to_string())),
+        }
+    }
+}
@@ -0,0 +1,416 @@
+//! Layout templates for the Blog Builder emitter module.
+//!
+//! Templates are HTML files containing tags between double braces:
+//!
+//! - `{{ name }}` inserts a variable, escaped unless it holds HTML
+//! - `{{> name }}` inserts the partial `partials/<name>.html`
+//! - `{{#if name}} ... {{else}} ... {{/if}}` checks whether a variable
+//!   is set and not empty
+//! - `{{#each name}} ... {{/each}}` repeats for every item of a list,
+//!   which is available as `{{ this }}`
+//! - `{{! comment }}` is left out of the output
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::{
+    escape,
+    Error,
+};
+
+/// Name of the layout used by pages that do not choose one.
+const DEFAULT_LAYOUT: &str = "default";
+
+/// Directory, within a templates directory, holding partials.
+const PARTIALS: &str = "partials";
+
+/// Maximum depth of partials within partials.
+const MAX_DEPTH: usize = 16;
+
+/// Value of a template variable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Text, escaped when inserted.
+    Text (String),
+
+    /// HTML, inserted as it is.
+    Html (String),
+
+    /// List of values, for `{{#each}}`.
+    List (Vec<Value>),
+
+    /// Boolean, for `{{#if}}`.
+    Boolean (bool),
+}
+
+impl Value {
+    /// Checks whether this value is set and not empty.
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Text (s) | Value::Html (s) => !s.is_empty(),
+            Value::List (v) => !v.is_empty(),
+            Value::Boolean (b) => *b,
+        }
+    }
+}
+
+/// Variables available to a template.
+pub type Context = HashMap<String, Value>;
+
+/// Part of a parsed template.
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Text (String),
+    Variable (String),
+    Partial (String),
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        name: String,
+        body: Vec<Node>,
+    },
+}
+
+/// Parsed template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// Block being parsed, which is closed by `{{/if}}` or `{{/each}}`.
+enum Block {
+    If {
+        name: String,
+        then: Option<Vec<Node>>,
+        line: usize,
+    },
+    Each {
+        name: String,
+        line: usize,
+    },
+}
+
+impl Template {
+    /// Parses a template, or returns a message describing the problem.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut stack: Vec<(Block, Vec<Node>)> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut rest = source;
+
+        while let Some (start) = rest.find("{{") {
+            let line = source[..source.len() - rest.len() + start].matches('\n').count() + 1;
+            if start > 0 {
+                nodes.push(Node::Text (rest[..start].to_string()));
+            }
+
+            let end = match rest[start..].find("}}") {
+                Some (e) => start + e,
+                None => return Err (format!("line {}: tag is not closed by `}}}}`", line)),
+            };
+            let tag = rest[start + 2..end].trim();
+            rest = &rest[end + 2..];
+
+            if let Some (name) = tag.strip_prefix("#if ") {
+                stack.push((Block::If {
+                    name: name.trim().to_string(),
+                    then: None,
+                    line,
+                }, nodes));
+                nodes = Vec::new();
+            } else if let Some (name) = tag.strip_prefix("#each ") {
+                stack.push((Block::Each {
+                    name: name.trim().to_string(),
+                    line,
+                }, nodes));
+                nodes = Vec::new();
+            } else if tag == "else" {
+                match stack.last_mut() {
+                    Some ((Block::If { then: then @ None, .. }, _)) => *then = Some (std::mem::take(&mut nodes)),
+                    _ => return Err (format!("line {}: `{{{{else}}}}` outside of `{{{{#if}}}}`", line)),
+                }
+            } else if tag == "/if" || tag == "/each" {
+                let node = match (stack.pop(), tag) {
+                    (Some ((Block::If { name, then, .. }, outer)), "/if") => {
+                        let inner = std::mem::replace(&mut nodes, outer);
+                        match then {
+                            Some (then) => Node::If {
+                                name,
+                                then,
+                                otherwise: inner,
+                            },
+                            None => Node::If {
+                                name,
+                                then: inner,
+                                otherwise: Vec::new(),
+                            },
+                        }
+                    },
+                    (Some ((Block::Each { name, .. }, outer)), "/each") => Node::Each {
+                        name,
+                        body: std::mem::replace(&mut nodes, outer),
+                    },
+                    _ => return Err (format!("line {}: unexpected `{{{{{}}}}}`", line, tag)),
+                };
+                nodes.push(node);
+            } else if let Some (name) = tag.strip_prefix('>') {
+                nodes.push(Node::Partial (name.trim().to_string()));
+            } else if tag.starts_with('!') {
+                // Comments are left out
+            } else if !tag.is_empty() && tag.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                nodes.push(Node::Variable (tag.to_string()));
+            } else {
+                return Err (format!("line {}: invalid tag `{{{{{}}}}}`", line, tag));
+            }
+        }
+
+        if !rest.is_empty() {
+            nodes.push(Node::Text (rest.to_string()));
+        }
+
+        match stack.pop() {
+            Some ((Block::If { line, .. }, _)) => Err (format!("line {}: `{{{{#if}}}}` is not closed by `{{{{/if}}}}`", line)),
+            Some ((Block::Each { line, .. }, _)) => Err (format!("line {}: `{{{{#each}}}}` is not closed by `{{{{/each}}}}`", line)),
+            None => Ok (Self {
+                nodes,
+            }),
+        }
+    }
+
+    /// Checks whether this template, not including its partials, uses a variable.
+    pub fn uses(&self, variable: &str) -> bool {
+        Self::nodes_use(&self.nodes, variable, None, 0)
+    }
+
+    /// Checks whether this template or any partial it inserts uses a variable.
+    pub fn uses_with(&self, variable: &str, templates: &Templates) -> bool {
+        Self::nodes_use(&self.nodes, variable, Some (templates), 0)
+    }
+
+    fn nodes_use(nodes: &[Node], variable: &str, templates: Option<&Templates>, depth: usize) -> bool {
+        nodes.iter().any(|n| match n {
+            Node::Text (_) => false,
+            Node::Variable (name) => name == variable,
+            Node::Partial (name) => match templates.and_then(|t| t.get_partial(name)) {
+                Some (p) if depth < MAX_DEPTH => Self::nodes_use(&p.nodes, variable, templates, depth + 1),
+                _ => false,
+            },
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => name == variable
+                || Self::nodes_use(then, variable, templates, depth)
+                || Self::nodes_use(otherwise, variable, templates, depth),
+            Node::Each {
+                name,
+                body,
+            } => name == variable || Self::nodes_use(body, variable, templates, depth),
+        })
+    }
+
+    /// Renders this template with the given variables and partials,
+    /// or returns a message describing the problem.
+    pub fn render(&self, context: &Context, templates: &Templates) -> Result<String, String> {
+        let mut output = String::new();
+        Self::render_nodes(&self.nodes, context, templates, &mut output, 0)?;
+        Ok (output)
+    }
+
+    fn render_nodes(
+        nodes: &[Node],
+        context: &Context,
+        templates: &Templates,
+        output: &mut String,
+        depth: usize,
+    ) -> Result<(), String> {
+        let get = |name: &str| match context.get(name) {
+            Some (v) => Ok (v),
+            None => Err (format!("unknown variable `{}`", name)),
+        };
+
+        for node in nodes {
+            match node {
+                Node::Text (t) => output.push_str(t),
+                Node::Variable (name) => match get(name)? {
+                    Value::Text (s) => output.push_str(&escape::text(s)),
+                    Value::Html (s) => output.push_str(s),
+                    Value::Boolean (b) => output.push_str(&b.to_string()),
+                    Value::List (_) => return Err (format!("variable `{}` is a list, use `{{{{#each {}}}}}`", name, name)),
+                },
+                Node::Partial (name) => {
+                    if depth >= MAX_DEPTH {
+                        return Err (format!("partials are nested more than {} deep", MAX_DEPTH));
+                    }
+                    let partial = match templates.get_partial(name) {
+                        Some (p) => p,
+                        None => return Err (format!("unknown partial `{}`", name)),
+                    };
+                    Self::render_nodes(&partial.nodes, context, templates, output, depth + 1)?;
+                },
+                Node::If {
+                    name,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = if get(name)?.is_truthy() { then } else { otherwise };
+                    Self::render_nodes(branch, context, templates, output, depth)?;
+                },
+                Node::Each {
+                    name,
+                    body,
+                } => {
+                    let items = match get(name)? {
+                        Value::List (v) => v,
+                        _ => return Err (format!("variable `{}` is not a list", name)),
+                    };
+                    for item in items {
+                        let mut context = context.to_owned();
+                        context.insert("this".to_string(), item.to_owned());
+                        Self::render_nodes(body, &context, templates, output, depth)?;
+                    }
+                },
+            }
+        }
+
+        Ok (())
+    }
+}
+
+/// Every layout and partial in a site's templates directories.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Templates {
+    /// Templates by name, their path relative to the templates directory
+    /// without the extension, such as `post` or `partials/header`.
+    templates: BTreeMap<String, Template>,
+
+    /// Sources of the templates, used to detect changes.
+    sources: BTreeMap<String, String>,
+}
+
+impl Templates {
+    /// Loads every `.html` file in the given templates directories.
+    /// Templates in earlier directories take precedence.
+    pub fn load(dirs: &[PathBuf]) -> Result<Self, Error> {
+        let mut templates = Self::default();
+
+        for dir in dirs.iter().rev() {
+            for entry in WalkDir::new(dir).sort_by_file_name() {
+                let entry = match entry {
+                    Ok (e) => e,
+                    Err (e) => return Err (Error::CannotReadDir (e)),
+                };
+                let path = entry.path();
+                if !entry.file_type().is_file() || path.extension().and_then(|e| e.to_str()) != Some ("html") {
+                    continue;
+                }
+
+                let name = path.strip_prefix(dir)
+                    .unwrap_or(path)
+                    .with_extension("")
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                let source = match fs::read_to_string(path) {
+                    Ok (s) => s,
+                    Err (e) => return Err (Error::CannotReadFile {
+                        path: path.to_path_buf(),
+                        source: e,
+                    }),
+                };
+
+                let template = Template::parse(&source).map_err(|message| Error::InvalidTemplate {
+                    path: path.to_path_buf(),
+                    message,
+                })?;
+
+                templates.templates.insert(name.to_owned(), template);
+                templates.sources.insert(name, source);
+            }
+        }
+
+        Ok (templates)
+    }
+
+    /// Gets the layout for a page, given the directory containing the page
+    /// relative to the site root and the layout chosen in its front matter.
+    ///
+    /// Without a chosen layout, a page uses the layout named after its
+    /// directory or the nearest parent directory that has one, or else
+    /// the default layout.  Returns `None` if there is no such layout,
+    /// and an error if the chosen layout does not exist.
+    pub fn get_layout(&self, dir: &Path, name: Option<&str>) -> Result<Option<&Template>, String> {
+        if let Some (name) = name {
+            return match self.templates.get(name) {
+                Some (t) if !name.starts_with(PARTIALS) => Ok (Some (t)),
+                _ => Err (format!("unknown template `{}`", name)),
+            };
+        }
+
+        let layout = dir.ancestors()
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(|d| d.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"))
+            .chain([DEFAULT_LAYOUT.to_string()])
+            .find_map(|n| self.templates.get(&n));
+
+        Ok (layout)
+    }
+
+    /// Gets a partial by name.
+    fn get_partial(&self, name: &str) -> Option<&Template> {
+        self.templates.get(&format!("{}/{}", PARTIALS, name))
+    }
+
+    /// Gets the source of every template, by name.
+    pub fn get_sources(&self) -> &BTreeMap<String, String> {
+        &self.sources
+    }
+}
+
+#[test]
+fn render_templates() {
+    let mut templates = Templates::default();
+    let mut add = |name: &str, source: &str| {
+        templates.templates.insert(name.to_string(), Template::parse(source).unwrap());
+    };
+    add("partials/tag", "<li>{{ this }}</li>");
+    add("default", "<h1>{{ title }}</h1>{{! comment }}{{#if description}}<p>{{ description }}</p>{{else}}<p>None</p>{{/if}}<ul>{{#each tags}}{{> tag}}{{/each}}</ul>{{ content }}");
+    add("posts", "Post layout");
+
+    let context = Context::from([
+        ("title".to_string(), Value::Text ("A & B".to_string())),
+        ("description".to_string(), Value::Text (String::new())),
+        ("tags".to_string(), Value::List (vec![Value::Text ("x".to_string()), Value::Text ("<y>".to_string())])),
+        ("content".to_string(), Value::Html ("<p>Hi</p>".to_string())),
+    ]);
+
+    let layout = templates.get_layout(Path::new(""), None).unwrap().unwrap();
+    assert_eq!(
+        layout.render(&context, &templates).unwrap(),
+        "<h1>A &amp; B</h1><p>None</p><ul><li>x</li><li>&lt;y&gt;</li></ul><p>Hi</p>",
+    );
+    assert!(layout.uses("tags") && !layout.uses("date"));
+    assert!(!layout.uses("this") && layout.uses_with("this", &templates) && !layout.uses_with("date", &templates));
+
+    assert_eq!(templates.get_layout(Path::new("posts/2024"), None).unwrap(), templates.templates.get("posts"));
+    assert!(templates.get_layout(Path::new(""), Some ("missing")).is_err());
+
+    assert_eq!(Template::parse("{{#if a}}\n{{#each b}}{{/if}}").unwrap_err(), "line 2: unexpected `{{/if}}`");
+    assert_eq!(Template::parse("{{#if a}}").unwrap_err(), "line 1: `{{#if}}` is not closed by `{{/if}}`");
+    assert_eq!(
+        Template::parse("{{ missing }}").unwrap().render(&context, &templates).unwrap_err(),
+        "unknown variable `missing`",
+    );
+}
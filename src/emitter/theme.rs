@@ -0,0 +1,243 @@
+//! Theme directories for the Blog Builder emitter module.
+//!
+//! A theme directory contains:
+//!
+//! - `style.css`, the stylesheet (required)
+//! - `links.html`, HTML included in the `<head>` of each page
+//! - `templates/`, layout templates, used where the site has none
+//! - `assets/`, files copied into the root of the website
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::Error;
+
+/// Developer's guide, which lists the selectors that styles must implement.
+const DEVELOPER_GUIDE: &str = include_str!("../../DEVELOPER_GUIDE.md");
+
+/// Heading of the section of the developer's guide listing required selectors.
+const REQUIRED_HEADING: &str = "### Required Implementations";
+
+#[derive(Clone, Debug, PartialEq)]
+/// Theme loaded from a directory.
+pub struct Theme {
+    /// Directory containing the theme.
+    dir: PathBuf,
+
+    /// Contents of the stylesheet.
+    css: String,
+
+    /// HTML included in the `<head>` of each page.
+    links: Option<String>,
+}
+
+impl Theme {
+    /// Loads a theme from a directory.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let css_path = dir.join("style.css");
+        let css = match fs::read_to_string(&css_path) {
+            Ok (c) => c,
+            Err (e) => return Err (Error::CannotFindFile {
+                path: css_path,
+                source: e,
+            }),
+        };
+
+        let links_path = dir.join("links.html");
+        let links = if links_path.is_file() {
+            match fs::read_to_string(&links_path) {
+                Ok (l) => Some (l),
+                Err (e) => return Err (Error::CannotReadFile {
+                    path: links_path,
+                    source: e,
+                }),
+            }
+        } else {
+            None
+        };
+
+        Ok (Self {
+            dir: dir.to_path_buf(),
+            css,
+            links,
+        })
+    }
+
+    pub fn get_dir(&self) -> PathBuf {
+        self.dir.to_owned()
+    }
+
+    pub fn get_css(&self) -> String {
+        self.css.to_owned()
+    }
+
+    pub fn get_links(&self) -> Option<String> {
+        self.links.to_owned()
+    }
+
+    /// Gets the theme's templates directory, if it has one.
+    pub fn get_template_dir(&self) -> Option<PathBuf> {
+        Some (self.dir.join("templates")).filter(|t| t.is_dir())
+    }
+
+    /// Copies the theme's assets into a directory, skipping
+    /// copies that are already up to date.
+    pub fn copy_assets(&self, destination: &Path) -> Result<(), Error> {
+        let assets = self.dir.join("assets");
+        if !assets.is_dir() {
+            return Ok (());
+        }
+
+        let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+
+        for entry in WalkDir::new(&assets) {
+            let entry = match entry {
+                Ok (e) => e,
+                Err (e) => return Err (Error::CannotReadDir (e)),
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path();
+            let target = destination.join(path.strip_prefix(&assets).unwrap_or(path));
+            if let (Some (src), Some (dst)) = (modified(path), modified(&target)) {
+                if dst >= src {
+                    continue;
+                }
+            }
+
+            if let Some (parent) = target.parent() {
+                if let Err (e) = fs::create_dir_all(parent) {
+                    return Err (Error::CannotCreateDir {
+                        path: parent.to_path_buf(),
+                        source: e,
+                    });
+                }
+            }
+
+            if let Err (e) = fs::copy(path, &target) {
+                return Err (Error::CannotCopyFile {
+                    path: path.to_path_buf(),
+                    source: e,
+                });
+            }
+        }
+
+        Ok (())
+    }
+
+    /// Lists the selectors required by the developer's guide that the
+    /// stylesheet does not implement, along with the control sequences
+    /// that rely on them.
+    pub fn get_missing_selectors(&self) -> Vec<(String, String)> {
+        let selectors = selectors(&self.css);
+
+        required_selectors().into_iter()
+            .filter(|(required, _)| !selectors.iter().any(|s| satisfies(s, required)))
+            .collect()
+    }
+}
+
+/// Reads the required selectors, and the control sequences that rely on
+/// them, from the developer's guide.
+fn required_selectors() -> Vec<(String, String)> {
+    DEVELOPER_GUIDE.split(REQUIRED_HEADING)
+        .nth(1)
+        .and_then(|s| s.split("```").nth(1))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.split_once("=>"))
+        .map(|(selector, control)| (selector.trim().to_string(), control.trim().to_string()))
+        .collect()
+}
+
+/// Lists the selectors in a stylesheet, including those within
+/// at-rules such as `@media`.
+fn selectors(css: &str) -> Vec<String> {
+    // Comments may contain braces, so remove them first
+    let mut uncommented = String::new();
+    let mut rest = css;
+    while let Some (start) = rest.find("/*") {
+        uncommented.push_str(&rest[..start]);
+        rest = rest[start..].find("*/").map_or("", |end| &rest[start + end + 2..]);
+    }
+    uncommented.push_str(rest);
+
+    // The text before each opening brace, after the previous brace or
+    // semicolon, is a selector list or an at-rule prelude
+    let mut selectors = Vec::new();
+    let mut start = 0;
+    for (i, c) in uncommented.char_indices() {
+        match c {
+            '{' => {
+                let prelude = uncommented[start..i].trim();
+                if !prelude.starts_with('@') {
+                    selectors.extend(prelude.split(',').map(|s| s.trim().to_string()));
+                }
+                start = i + 1;
+            },
+            '}' | ';' => start = i + 1,
+            _ => (),
+        }
+    }
+
+    selectors
+}
+
+/// Checks whether a selector styles the elements matched by a required
+/// selector such as `h1` or `div.tile`.
+///
+/// The last compound selector must have the required element name, or
+/// none if the required selector has a class, and every required class.
+fn satisfies(selector: &str, required: &str) -> bool {
+    let last = selector.rsplit([' ', '>', '+', '~']).next().unwrap_or_default();
+
+    // Pseudo-classes and attributes still style the element
+    let last = last.split([':', '[']).next().unwrap_or_default();
+
+    let mut required_parts = required.split('.');
+    let required_name = required_parts.next().unwrap_or_default();
+    let required_classes = required_parts.collect::<Vec<&str>>();
+
+    let mut parts = last.split('.');
+    let name = parts.next().unwrap_or_default();
+    let classes = parts.collect::<Vec<&str>>();
+
+    let name_matches = name.eq_ignore_ascii_case(required_name)
+        || (name.is_empty() && !required_classes.is_empty());
+
+    name_matches && required_classes.iter().all(|c| classes.contains(c))
+}
+
+#[test]
+fn validate_theme() {
+    let required = required_selectors();
+    assert!(required.contains(&("div.tiles".to_string(), "\\tiles".to_string())));
+    assert!(required.contains(&("h6.footer".to_string(), "\\footer".to_string())));
+
+    let theme = |css: &str| Theme {
+        dir: PathBuf::new(),
+        css: css.to_string(),
+        links: None,
+    };
+
+    let complete = "/* h1 { */ h1, h2 { } h3:hover, body h4 { } @media (max-width: 600px) { h5 { } h6 { } }
+        p { } p a { } .tiles { } div.menu > a, div.menu { } div.tile { } h6.footer { }";
+    assert!(theme(complete).get_missing_selectors().is_empty());
+
+    assert_eq!(
+        theme("h1 { } h6 { } div.tiles a { } .footer-link { }").get_missing_selectors()
+            .into_iter()
+            .map(|(s, _)| s)
+            .collect::<Vec<String>>(),
+        vec!["h2", "h3", "h4", "h5", "p", "div.tiles", "div.menu", "div.tile", "h6.footer"],
+    );
+
+    // The starting point for new styles implements every required selector
+    assert_eq!(theme(include_str!("metadata/styles/template.css")).get_missing_selectors(), vec![]);
+}
@@ -1,62 +1,464 @@
 //! Error handler for the Blog Builder.
 
 use std::{
+    error,
     fmt::{
         Display,
         Formatter,
         Result,
     },
+    io,
+    path::{Path, PathBuf},
     process::exit,
 };
 
+use crate::parser::{
+    Span,
+    Token,
+    TokenClass,
+};
+
+#[derive(Debug)]
 pub enum Error {
-    CannotGetWorkingDirectory,
-    UnrecognizedToken,
-    UnrecognizedControlSequence,
-    TooManyHashes,
-    UnexpectedEof,
-    ExpectedTokenOfClass,
-    CannotFindFile,
-    CannotReadFile,
-    CannotOpenFile,
-    CannotWriteFile,
-    CannotExtractFileStem,
-    CannotReadDir,
+    UnknownCommand (String),
+    UnknownFlag {
+        flag: String,
+        command: String,
+    },
+    MissingValue (String),
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    MissingInput (String),
+    MissingOutputDir,
+    UnexpectedArgument (String),
+    UnknownStyle (String),
+    InvalidEnvironmentVariable {
+        name: String,
+        value: String,
+    },
+    CannotGetWorkingDirectory (io::Error),
+    UnrecognizedToken (Token),
+    UnrecognizedControlSequence (Token),
+    TooManyHashes (Token),
+    UnexpectedEof (Span),
+    ExpectedTokenOfClass {
+        expected: TokenClass,
+        found: Token,
+    },
+    InvalidFrontMatter {
+        span: Span,
+        message: String,
+    },
+    CannotFindFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CannotReadFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CannotOpenFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CannotWriteFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CannotReadDir (walkdir::Error),
+    CannotCreateDir {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CannotCopyFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CannotBindAddress {
+        address: String,
+        source: io::Error,
+    },
+    InvalidConfig {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidTemplate {
+        path: PathBuf,
+        message: String,
+    },
+    CannotRenderTemplate (String),
+    InvalidTable (String),
+    InvalidMacro {
+        name: String,
+        span: Span,
+        message: String,
+    },
+    UnrenderedExtension (String),
+    IncludeCycle {
+        span: Span,
+        cycle: Vec<PathBuf>,
+    },
+    InMacro {
+        name: String,
+        span: Span,
+        source: Box<Error>,
+    },
+    InFile {
+        path: PathBuf,
+        span: Option<Span>,
+        excerpt: String,
+        source: Box<Error>,
+    },
+    Multiple (Vec<Error>),
+    BuildFailed {
+        failed: usize,
+        total: usize,
+        files: usize,
+        source: Box<Error>,
+    },
 }
 
 impl Error {
-    pub fn throw(&self) -> ! {
-        println!("{}", self);
-        
-        exit(0);
+    /// Gets the location in the source text at which this error occurred, if known.
+    pub fn get_span(&self) -> Option<Span> {
+        use Error::*;
+
+        match self {
+            UnrecognizedToken (t) => Some (t.get_span()),
+            UnrecognizedControlSequence (t) => Some (t.get_span()),
+            TooManyHashes (t) => Some (t.get_span()),
+            UnexpectedEof (s) => Some (*s),
+            ExpectedTokenOfClass { found, .. } => Some (found.get_span()),
+            InvalidFrontMatter { span, .. } => Some (*span),
+            InvalidMacro { span, .. }
+                | IncludeCycle { span, .. }
+                | InMacro { span, .. } => Some (*span),
+            InFile { span, .. } => *span,
+            _ => None,
+        }
+    }
+
+    /// Combines a list of errors into a single error.
+    pub fn from_list(errors: Vec<Error>) -> Self {
+        let mut errors = errors.into_iter()
+            .flat_map(|e| match e {
+                Error::Multiple (v) => v,
+                _ => vec![e],
+            })
+            .collect::<Vec<Error>>();
+
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            Error::Multiple (errors)
+        }
+    }
+
+    /// Counts the individual errors contained in this error.
+    pub fn count(&self) -> usize {
+        match self {
+            Error::Multiple (v) => v.iter().map(|e| e.count()).sum(),
+            _ => 1,
+        }
+    }
+
+    /// Attributes this error to the expansion of a macro invoked at the
+    /// given span, since the expansion does not appear in the source.
+    pub fn in_macro(self, name: &str, span: Span) -> Self {
+        match self {
+            // Errors in other files are reported there
+            Error::InFile { .. } => self,
+            Error::Multiple (v) => Error::Multiple (
+                v.into_iter()
+                    .map(|e| e.in_macro(name, span))
+                    .collect()
+            ),
+            // A macro that expands itself is only named once
+            Error::InvalidMacro {
+                name: n,
+                message,
+                ..
+            } if n == name => Error::InvalidMacro {
+                name: n,
+                span,
+                message,
+            },
+            Error::InMacro {
+                name: n,
+                source,
+                ..
+            } if n == name => Error::InMacro {
+                name: n,
+                span,
+                source,
+            },
+            e => Error::InMacro {
+                name: name.to_string(),
+                span,
+                source: Box::new(e),
+            },
+        }
+    }
+
+    /// Attaches the file name and source text to this error, so that
+    /// it can be reported with its position and an excerpt of the source.
+    pub fn in_file(self, path: &Path, contents: &str) -> Self {
+        match self {
+            Error::InFile { .. } => return self,
+            Error::Multiple (v) => return Error::Multiple (
+                v.into_iter()
+                    .map(|e| e.in_file(path, contents))
+                    .collect()
+            ),
+            _ => (),
+        }
+
+        let span = self.get_span();
+        let excerpt = match span {
+            Some (s) => Self::excerpt(s, contents),
+            None => String::new(),
+        };
+
+        Error::InFile {
+            path: path.to_path_buf(),
+            span,
+            excerpt,
+            source: Box::new(self),
+        }
+    }
+
+    /// Renders the source line containing the given span,
+    /// with the span underlined by carets.
+    fn excerpt(span: Span, contents: &str) -> String {
+        let offset = span.offset.min(contents.len());
+        let line_start = contents[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = contents[offset..].find('\n').map_or(contents.len(), |i| offset + i);
+        let underline_end = (offset + span.length).min(line_end).max(offset);
+
+        let line = &contents[line_start..line_end];
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        let indent = contents[line_start..offset].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let carets = "^".repeat(contents[offset..underline_end].chars().count().max(1));
+
+        format!(
+            "{} |\n{} | {}\n{} | {}{}",
+            gutter,
+            span.line,
+            line,
+            gutter,
+            indent,
+            carets,
+        )
+    }
+
+    /// Gets the process exit code for this error.
+    /// 
+    /// - `1`: one or more pages could not be compiled
+    /// - `2`: invalid command-line arguments or environment variables
+    /// - `3`: invalid configuration file or template
+    /// - `4`: a file or directory could not be read or written
+    pub fn exit_code(&self) -> i32 {
+        use Error::*;
+
+        match self {
+            UnknownCommand (_)
+                | UnknownFlag { .. }
+                | MissingValue (_)
+                | InvalidValue { .. }
+                | MissingInput (_)
+                | MissingOutputDir
+                | UnexpectedArgument (_)
+                | UnknownStyle (_)
+                | InvalidEnvironmentVariable { .. } => 2,
+            InvalidConfig { .. } | InvalidTemplate { .. } => 3,
+            CannotGetWorkingDirectory (_)
+                | CannotFindFile { .. }
+                | CannotReadFile { .. }
+                | CannotOpenFile { .. }
+                | CannotWriteFile { .. }
+                | CannotReadDir (_)
+                | CannotCreateDir { .. }
+                | CannotCopyFile { .. }
+                | CannotBindAddress { .. } => 4,
+            InMacro { source, .. } | InFile { source, .. } => source.exit_code(),
+            _ => 1,
+        }
     }
 
-    pub fn throw_msg<S: Display + ?Sized>(&self, msg: &S) -> ! {
-        println!("{}: {}", self, msg);
-        
-        exit(0);
+    /// Prints this error and its sources, then exits with its exit code.
+    pub fn throw(&self) -> ! {
+        eprintln!("{}", self);
+
+        let mut source = error::Error::source(self);
+        while let Some (s) = source {
+            eprintln!("    caused by: {}", s);
+            source = s.source();
+        }
+
+        exit(self.exit_code());
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if let Error::Multiple (v) = self {
+            let errors = v.iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>();
+            return write!(f, "{}", errors.join("\n"));
+        }
+
+        write!(f, "[ERROR] {}", self.message())
+    }
+}
+
+impl Error {
+    /// Gets the message describing this error, without the `[ERROR]` prefix.
+    fn message(&self) -> String {
         use Error::*;
 
         let msg = match self {
-            CannotGetWorkingDirectory => "cannot get working directory",
-            UnrecognizedToken => "unrecognized token",
-            UnrecognizedControlSequence => "unrecongized control sequence",
-            TooManyHashes => "too many hashes",
-            UnexpectedEof => "unexpected end of file",
-            ExpectedTokenOfClass => "expected token of class",
-            CannotFindFile => "cannot find file",
-            CannotReadFile => "cannot read file",
-            CannotOpenFile => "cannot open file",
-            CannotWriteFile => "cannot write to file",
-            CannotExtractFileStem => "cannot extract file stem",
-            CannotReadDir => "cannot read input directory",
+            UnknownCommand (c) => format!("unknown command `{}` (run `blog help` for a list of commands)", c),
+            UnknownFlag {
+                flag,
+                command,
+            } => format!("unknown flag `{}` for command `{}` (run `blog help {}` for a list of flags)", flag, command, command),
+            MissingValue (f) => format!("missing value for flag `{}`", f),
+            InvalidValue {
+                flag,
+                value,
+            } => format!("invalid value `{}` for flag `{}`", value, flag),
+            MissingInput (c) => format!("missing input path for command `{}` (run `blog help {}` for usage)", c, c),
+            MissingOutputDir => "the `serve` command needs an output directory, so that only generated files are served (set `--out` or `out` in blog.toml)".to_string(),
+            UnexpectedArgument (a) => format!("unexpected argument `{}`", a),
+            UnknownStyle (s) => format!("unknown style `{}` (expected citizen, modern, tech, truth or a theme directory)", s),
+            InvalidEnvironmentVariable {
+                name,
+                value,
+            } => format!("invalid value `{}` for environment variable `{}`", value, name),
+            CannotGetWorkingDirectory (_) => "cannot get working directory".to_string(),
+            UnrecognizedToken (t) => format!(
+                "unrecognized token: `{}` (of type {})",
+                t.get_value().escape_debug(),
+                t.get_class(),
+            ),
+            UnrecognizedControlSequence (t) => format!("unrecognized control sequence: `\\{}`", t.get_value()),
+            TooManyHashes (t) => format!("too many hashes: {}", t.get_value()),
+            UnexpectedEof (_) => "unexpected end of file".to_string(),
+            ExpectedTokenOfClass {
+                expected,
+                found,
+            } => format!(
+                "expected token of class: {}, found: {} (`{}`)",
+                expected,
+                found.get_class(),
+                found.get_value().escape_debug(),
+            ),
+            InvalidFrontMatter { message, .. } => format!("invalid front matter: {}", message),
+            CannotFindFile { path, .. } => format!("cannot find file: {}", path.display()),
+            CannotReadFile { path, .. } => format!("cannot read file: {}", path.display()),
+            CannotOpenFile { path, .. } => format!("cannot open file: {}", path.display()),
+            CannotWriteFile { path, .. } => format!("cannot write to file: {}", path.display()),
+            CannotReadDir (_) => "cannot read input directory".to_string(),
+            CannotCreateDir { path, .. } => format!("cannot create directory: {}", path.display()),
+            CannotCopyFile { path, .. } => format!("cannot copy file: {}", path.display()),
+            CannotBindAddress { address, .. } => format!("cannot listen on address: {}", address),
+            InvalidConfig {
+                path,
+                message,
+            } => format!("invalid configuration in {}: {}", path.display(), message),
+            InvalidTemplate {
+                path,
+                message,
+            } => format!("invalid template {}: {}", path.display(), message),
+            CannotRenderTemplate (m) => format!("cannot render template: {}", m),
+            InvalidTable (m) => format!("invalid table: {}", m),
+            InvalidMacro {
+                name,
+                message,
+                ..
+            } => format!("invalid macro `\\{}`: {}", name, message),
+            UnrenderedExtension (n) => format!("cannot render `\\{}`: no renderer is registered for it", n),
+            IncludeCycle {
+                cycle,
+                ..
+            } => format!(
+                "include cycle: {}",
+                cycle.iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(" includes "),
+            ),
+            InMacro {
+                name,
+                source,
+                ..
+            } => format!("in macro `\\{}`: {}", name, source.message()),
+            InFile {
+                path,
+                span: Some (s),
+                excerpt,
+                source,
+            } => format!(
+                "{}:{}:{}: {}\n{}",
+                path.display(),
+                s.line,
+                s.column,
+                source.message(),
+                excerpt,
+            ),
+            InFile {
+                path,
+                span: None,
+                source,
+                ..
+            } => format!("{}: {}", path.display(), source.message()),
+            Multiple (v) => format!("{} errors", v.len()),
+            BuildFailed {
+                failed,
+                total,
+                files: 0,
+                ..
+            } => format!("build failed: {} of {} pages could not be compiled", failed, total),
+            BuildFailed {
+                failed,
+                total,
+                files,
+                ..
+            } => format!(
+                "build failed: {} of {} pages could not be compiled, and {} other files could not be copied",
+                failed,
+                total,
+                files,
+            ),
         };
 
-        write!(f, "[ERROR] {}", msg)
+        msg
     }
-}
\ No newline at end of file
+}
+
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+
+        match self {
+            CannotGetWorkingDirectory (e) => Some (e),
+            CannotFindFile { source, .. } => Some (source),
+            CannotReadFile { source, .. } => Some (source),
+            CannotOpenFile { source, .. } => Some (source),
+            CannotWriteFile { source, .. } => Some (source),
+            CannotReadDir (e) => Some (e),
+            CannotCreateDir { source, .. } => Some (source),
+            CannotCopyFile { source, .. } => Some (source),
+            CannotBindAddress { source, .. } => Some (source),
+            InMacro { source, .. } | InFile { source, .. } => source.source(),
+            _ => None,
+        }
+    }
+}
@@ -1,34 +1,76 @@
 //! Main library for the Blog Builder.
 
+mod cache;
 mod emitter;
 mod error;
 mod parser;
+mod serve;
+#[cfg(test)]
+mod test_dir;
+mod watcher;
 
 use std::{
+    collections::HashMap,
     ffi::OsStr,
     fs,
-    io::{Read, Write},
-    process,
-};
-
-use text_diff::{
-    diff,
-    Difference,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
 };
 
+use chrono::NaiveDate;
 use walkdir::WalkDir;
 
+pub use cache::{
+    Cache,
+    CACHE_FILENAME,
+};
+
 pub use error::Error;
 
 pub use parser::{
+    Alignment,
+    ControlHandler,
+    Extension,
     Parser,
+    ParserBuilder,
+    PrefixParselet,
     Expression,
+    PageMeta,
+    Row,
+    Span,
+    Token,
+    TokenClass,
+    Tokenizer,
+};
+
+pub use serve::{
+    serve,
+    serve_with,
+};
+
+pub use watcher::{
+    watch,
+    watch_with,
 };
 
 pub use emitter::{
+    escape,
+    Config,
+    CONFIG_FILENAME,
     Emitter,
+    History,
     Metadata,
     CommandOption,
+    Renderer,
+    Renderers,
+    Template,
+    Templates,
+    Theme,
 };
 
 /// Version number.
@@ -37,89 +79,116 @@ const VERSION: &str = "0.1.0";
 /// Help menu.
 const HELP: &str = include_str!("help.txt");
 
+/// Help menu for the `compile` command.
+const HELP_COMPILE: &str = include_str!("help/compile.txt");
+
+/// Help menu for the `build` command.
+const HELP_BUILD: &str = include_str!("help/build.txt");
+
+/// Help menu for the `serve` command.
+const HELP_SERVE: &str = include_str!("help/serve.txt");
+
+/// Help menu for the `watch` command.
+const HELP_WATCH: &str = include_str!("help/watch.txt");
+
 /// Compiles a file, given its filename.
-pub fn compile(metadata: &Metadata) {
-    let filename = metadata.get_input();
-    println!("Compiling {}", filename.display());
+pub fn compile(metadata: &Metadata) -> Result<(), Error> {
+    compile_with(metadata, &Parser::builder(), &Renderers::new())
+}
 
-    // Opens the file provided and reads its contents
-    let mut file = match fs::OpenOptions::new()
-        .read(true)
-        .open(&filename)
-    {
-        Ok (f) => f,
-        Err (_) => Error::CannotFindFile.throw_msg(&filename.display()),
-    };
-    let mut contents = String::new();
-    match file.read_to_string(&mut contents) {
-        Ok (_) => (),
-        Err (_) => Error::CannotReadFile.throw(),
-    }
+/// Compiles a file, given its filename, parsing it with parsers built by
+/// the given builder and rendering its extensions with the given renderers.
+pub fn compile_with(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Result<(), Error> {
+    metadata.prepare_output()?;
+    println!("Compiling {}", metadata.get_input().display());
+    compile_page(metadata, parser, renderers).map(|_| ())
+}
+
+/// Outcome of compiling a page.
+struct Compiled {
+    /// Files read by the page.
+    dependencies: Vec<PathBuf>,
+
+    /// File the page was written to, or `None` if it is a draft.
+    output: Option<PathBuf>,
+
+    /// Date shown on the page, if it was found from the page's files
+    /// rather than its front matter.
+    date: Option<NaiveDate>,
+}
+
+/// Compiles a file without printing its progress.
+/// 
+/// Drafts are only written by the `compile` command, and are
+/// left out of builds.
+fn compile_page(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Result<Compiled, Error> {
+    let filename = metadata.get_input();
+    let contents = read_source(&filename)?;
 
     // Creates a new parser and parses the file contents
-    let parser = Parser::new();
-    let expressions = parser.parse(&contents);
+    let parser = page_parser(metadata, &filename, parser)?;
+    let (mut meta, expressions, mut errors) = parser.parse_page(&contents);
+    let dependencies = expressions.iter()
+        .flat_map(|e| e.get_dependencies())
+        .collect();
 
-    let pagename = if expressions.len() > 0 {
-        match expressions[0].clone() {
-            Expression::Pagename (s) => s.to_owned(),
-            _ => "New Page".to_string(),
-        }
-    } else {
-        "New Page".to_string()
+    // Write the emitter's result into an HTML file, named by its slug if it has one
+    let output_filename = match &meta.slug {
+        Some (slug) => metadata.get_output_path(&filename).with_file_name(format!("{}.html", slug)),
+        None => metadata.get_output_path(&filename).with_extension("html"),
     };
 
-    // Creates a new emitter and emits the parser's result
-    let emitter = Emitter::new(metadata);
-    let emitted = emitter.emit(expressions, &pagename);
+    // A page that has become a draft is no longer published,
+    // so its output from earlier builds is removed
+    if meta.draft && metadata.get_command_option() != CommandOption::Compile {
+        match fs::remove_file(&output_filename) {
+            Err (e) if e.kind() != io::ErrorKind::NotFound => return Err (Error::CannotWriteFile {
+                path: output_filename,
+                source: e,
+            }),
+            _ => (),
+        }
 
-    // Write the emitter's result into an HTML file
-    let output_filename = &filename.with_extension("html");
+        return Ok (Compiled {
+            dependencies,
+            output: None,
+            date: None,
+        });
+    }
 
-    // Are we updating the output file?
-    // 
-    // Read output file.. if the output file has updated,
-    // overwrite it with the new date
-    let updated = match fs::read(&output_filename) {
-        Ok (f) => {
-            let (_dist, changelist) = diff(
-                &String::from_utf8(f).unwrap(),
-                &String::from_utf8(emitted.clone()).unwrap(),
-                "\n",
-            );
-
-            // Changes that *do not apply* to updated date
-            let mut changes = 0;
-
-            let mut is_new_date = false;
-
-            // Update in the following conditions:
-            // - Last Updated date added
-            // - Last Updated date removed
-            // - Something other than Last Updated date is new
-            for change in &changelist {
-                if let Difference::Same (_) = change {
-                    // do nothing
-                } else if let Difference::Add (a) = change {
-                    if a.contains("last-updated-date") && !a.contains("\n\n") {
-                        is_new_date = !is_new_date;
-                    } else {
-                        changes += 1;
-                    }
-                } else if let Difference::Rem (r) = change {
-                    if r.contains("last-updated-date") && !r.contains("\n\n"){
-                        is_new_date = !is_new_date;
-                    } else {
-                        changes += 1;
-                    }
-                }
-            }
+    // The front matter title takes precedence over `\pagename`
+    if meta.title.is_none() {
+        meta.title = expressions.iter().find_map(|e| match e {
+            Expression::Pagename (s) => Some (s.to_owned()),
+            _ => None,
+        });
+    }
 
-            changes > 0 || is_new_date
+    // Creates a new emitter and emits the parser's result,
+    // reporting parse and emit errors together
+    let emitter = Emitter::new(metadata).with_renderers(renderers);
+    let emitted = match emitter.emit(expressions, &meta) {
+        Ok (e) if errors.is_empty() => e,
+        Ok (_) => return Err (Error::from_list(errors).in_file(&filename, &contents)),
+        Err (e) => {
+            errors.push(e);
+            return Err (Error::from_list(errors).in_file(&filename, &contents));
         },
+    };
 
-        // If you can't find the original file, you do need
-        // to "update" (by creating a new file)
+    if let Some (parent) = output_filename.parent() {
+        if let Err (e) = fs::create_dir_all(parent) {
+            return Err (Error::CannotCreateDir {
+                path: parent.to_path_buf(),
+                source: e,
+            });
+        }
+    }
+
+    // Only rewrite the output file if it has changed, so that
+    // its modification time reflects the last real change
+    let updated = match fs::read(&output_filename) {
+        Ok (f) => f != emitted,
         Err (_) => true,
     };
 
@@ -131,38 +200,578 @@ pub fn compile(metadata: &Metadata) {
             .open(&output_filename)
         {
             Ok (f) => f,
-            Err (_) => Error::CannotOpenFile.throw_msg(&output_filename.display()),
+            Err (e) => return Err (Error::CannotOpenFile {
+                path: output_filename,
+                source: e,
+            }),
         };
 
-        match output.write_all(&emitted) {
-            Ok (_) => (),
-            Err (_) => Error::CannotWriteFile.throw(),
+        if let Err (e) = output.write_all(&emitted) {
+            return Err (Error::CannotWriteFile {
+                path: output_filename,
+                source: e,
+            });
         }
     }
+
+    Ok (Compiled {
+        dependencies,
+        output: Some (output_filename),
+        date: emitter.get_found_date(),
+    })
+}
+
+/// Compiles a page of a site as `compile_with` does, and updates the
+/// build cache with the outcome.
+pub(crate) fn compile_cached(
+    metadata: &Metadata,
+    parser: &ParserBuilder,
+    renderers: &Renderers,
+    cache: &mut Cache,
+) -> Result<(), Error> {
+    let page = metadata.get_input();
+    println!("Compiling {}", page.display());
+    update_cache(cache, metadata, &page, compile_page(metadata, parser, renderers)).map(|_| ())
+}
+
+/// Records a compiled page in the build cache, or forgets it if it is
+/// a draft or failed to compile.
+fn update_cache(cache: &mut Cache, metadata: &Metadata, page: &Path, result: Result<Compiled, Error>) -> Result<Status, Error> {
+    match result {
+        Ok (Compiled { dependencies, output: Some (output), date }) => {
+            cache.record(metadata, page, dependencies, output, date);
+            Ok (Status::Compiled)
+        },
+        Ok (Compiled { output: None, .. }) => {
+            cache.remove(metadata, page);
+            Ok (Status::Draft)
+        },
+        Err (err) => {
+            cache.remove(metadata, page);
+            Err (err)
+        },
+    }
+}
+
+/// Lists the files that a page reads when it is compiled, such as
+/// code files, without compiling it.
+pub fn dependencies(metadata: &Metadata) -> Result<Vec<PathBuf>, Error> {
+    dependencies_with(metadata, &Parser::builder())
+}
+
+/// Lists the files that a page reads when it is compiled, parsing it
+/// with a parser built by the given builder.
+pub fn dependencies_with(metadata: &Metadata, parser: &ParserBuilder) -> Result<Vec<PathBuf>, Error> {
+    let filename = metadata.get_input();
+    let contents = read_source(&filename)?;
+
+    // Dependencies are collected even if parts of the page are malformed
+    let (_, expressions, _) = page_parser(metadata, &filename, parser)?.parse_page(&contents);
+
+    Ok (expressions.iter()
+        .flat_map(|e| e.get_dependencies())
+        .collect())
+}
+
+/// Creates a parser for a page, which reads files relative to the page
+/// and knows the site's macros.
+fn page_parser(metadata: &Metadata, filename: &Path, parser: &ParserBuilder) -> Result<Parser, Error> {
+    let parser = parser.build().in_page(filename);
+
+    if let Some ((path, contents)) = metadata.get_macros() {
+        parser.load_macros(&path, &contents)?;
+    }
+
+    Ok (parser)
+}
+
+/// Opens a source file and reads its contents.
+fn read_source(filename: &Path) -> Result<String, Error> {
+    let mut file = match fs::OpenOptions::new()
+        .read(true)
+        .open(filename)
+    {
+        Ok (f) => f,
+        Err (e) => return Err (Error::CannotFindFile {
+            path: filename.to_path_buf(),
+            source: e,
+        }),
+    };
+    let mut contents = String::new();
+    if let Err (e) = file.read_to_string(&mut contents) {
+        return Err (Error::CannotReadFile {
+            path: filename.to_path_buf(),
+            source: e,
+        });
+    }
+
+    Ok (contents)
+}
+
+/// Work found in the input directory by a build.
+enum Task {
+    /// Page to compile, with its position among the pages being compiled.
+    Compile (PathBuf, usize),
+
+    /// Page that is unchanged since the last build.
+    Unchanged (PathBuf),
+
+    /// Other file, copied into the output directory.
+    Copy (PathBuf),
+
+    /// Entry that could not be read.
+    Unreadable (PathBuf, Error),
+}
+
+/// Outcome of a page in a build.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Status {
+    /// Page was compiled.
+    Compiled,
+
+    /// Page was skipped because it is unchanged since the last build.
+    Unchanged,
+
+    /// Page was skipped because it is a draft.
+    Draft,
 }
 
 /// Builds a directory into a website.
-pub fn build(metadata: &Metadata) {
+/// 
+/// Every page is compiled, even if some pages fail.  Pages are compiled
+/// in parallel, but progress and errors are printed in the order of the
+/// source tree and summarized at the end of the build.  If there is an
+/// output directory, every other file is copied into it.
+/// 
+/// Pages whose inputs are unchanged since the last build are skipped,
+/// unless the build is forced.
+pub fn build(metadata: &Metadata) -> Result<(), Error> {
+    build_with(metadata, &Parser::builder(), &Renderers::new())
+}
+
+/// Builds a directory into a website, parsing its pages with parsers
+/// built by the given builder and rendering their extensions with the
+/// given renderers.
+pub fn build_with(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Result<(), Error> {
+    metadata.prepare_output()?;
     let dir = metadata.get_input();
+    let mut cache = Cache::load(metadata, parser, renderers);
+    let history = History::new(&dir);
 
-    for entry in WalkDir::new(dir) {
+    let mut tasks = Vec::new();
+    let mut pages = Vec::new();
+    for entry in walk(metadata) {
         match entry {
-            Ok (e) => if e.path().is_file()
-                && e.path().extension() == Some (OsStr::new("txt"))
-            {
-                let metadata = metadata.with_input(e.path().to_path_buf());
-                compile(&metadata);
+            Ok (e) => if e.path().is_file() && is_page(metadata, e.path()) {
+                let page = e.path().to_path_buf();
+                if !metadata.is_force() && cache.is_fresh(metadata, &page, &history) {
+                    tasks.push(Task::Unchanged (page));
+                } else {
+                    tasks.push(Task::Compile (page.to_owned(), pages.len()));
+                    pages.push(page);
+                }
+            } else if e.path().is_file() && metadata.get_out().is_some() {
+                tasks.push(Task::Copy (e.path().to_path_buf()));
             },
-            Err (_) => Error::CannotReadDir.throw(),
+            Err (e) => {
+                let path = e.path().map_or(dir.clone(), Path::to_path_buf);
+                tasks.push(Task::Unreadable (path, Error::CannotReadDir (e)));
+            },
+        }
+    }
+
+    let jobs = metadata.get_jobs().min(pages.len()).max(1);
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut results = Vec::new();
+
+    thread::scope(|scope| {
+        // Each worker takes the next page that has not been compiled yet
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let pages = &pages;
+            let next = &next;
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let page = match pages.get(i) {
+                    Some (p) => p,
+                    None => break,
+                };
+                let result = compile_page(&metadata.with_input(page.to_owned()), parser, renderers);
+                if sender.send((i, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Results arrive in any order, so they are held back
+        // until every result before them has been reported
+        let mut compiled = HashMap::new();
+        for task in tasks {
+            match task {
+                Task::Compile (page, i) => {
+                    while !compiled.contains_key(&i) {
+                        match receiver.recv() {
+                            Ok ((j, r)) => compiled.insert(j, r),
+                            Err (_) => break,
+                        };
+                    }
+
+                    println!("Compiling {}", page.display());
+                    let result = match compiled.remove(&i) {
+                        Some (r) => update_cache(&mut cache, metadata, &page, r),
+                        None => unreachable!("every page is compiled by a worker"),
+                    };
+                    if let Err (err) = &result {
+                        eprintln!("{}", err);
+                    }
+                    results.push((page, result));
+                },
+                Task::Unchanged (page) => results.push((page, Ok (Status::Unchanged))),
+                Task::Copy (path) => if let Err (err) = copy_asset(metadata, &path) {
+                    eprintln!("{}", err);
+                    results.push((path, Err (err)));
+                },
+                Task::Unreadable (path, err) => {
+                    eprintln!("{}", err);
+                    results.push((path, Err (err)));
+                },
+            }
+        }
+    });
+
+    // A cache that cannot be written only makes the next build slower
+    if let Err (e) = cache.save(metadata) {
+        eprintln!("{}", e);
+    }
+
+    summarize(metadata, results)
+}
+
+/// Walks the input directory, skipping the output directory,
+/// the `.git` directory and the build cache.
+pub(crate) fn walk(metadata: &Metadata) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> {
+    let out = metadata.get_out().and_then(|o| o.canonicalize().ok());
+
+    WalkDir::new(metadata.get_input())
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(move |e| {
+            let skipped = e.depth() > 0 && (e.file_name() == ".git" || e.file_name() == CACHE_FILENAME);
+            let output = e.file_type().is_dir()
+                && out.is_some()
+                && e.path().canonicalize().ok() == out;
+            !skipped && !output
+        })
+}
+
+/// Checks whether a file is a page, to be compiled.
+///
+/// Hidden files, and files in hidden directories such as `.well-known/`,
+/// are never pages, so that they are copied as they are.
+pub(crate) fn is_page(metadata: &Metadata, path: &Path) -> bool {
+    let hidden = path.strip_prefix(metadata.get_root())
+        .unwrap_or(path)
+        .components()
+        .any(|c| matches!(c, Component::Normal (n) if n.to_string_lossy().starts_with('.')));
+
+    path.extension() == Some (OsStr::new("txt"))
+        && !hidden
+        && !is_layout(metadata, path)
+        && !is_partial(metadata, path)
+}
+
+/// Checks whether a file is in the partials directory, to be included
+/// in pages rather than compiled or copied.
+pub(crate) fn is_partial(metadata: &Metadata, path: &Path) -> bool {
+    let dir = match metadata.get_partials_dir().and_then(|d| d.canonicalize().ok()) {
+        Some (d) => d,
+        None => return false,
+    };
+
+    path.canonicalize().is_ok_and(|p| p.starts_with(dir))
+}
+
+/// Checks whether a file is part of the site's layout, in the templates
+/// directory, the theme directory or the macros file, rather than its content.
+pub(crate) fn is_layout(metadata: &Metadata, path: &Path) -> bool {
+    let path = match path.canonicalize() {
+        Ok (p) => p,
+        Err (_) => return false,
+    };
+
+    metadata.get_template_dir()
+        .into_iter()
+        .chain(metadata.get_theme().map(|t| t.get_dir()))
+        .chain(metadata.get_macros().map(|(m, _)| m))
+        .filter_map(|d| d.canonicalize().ok())
+        .any(|d| path.starts_with(d))
+}
+
+/// Copies a file from the source tree into the output directory,
+/// unless the copy is already up to date.
+pub(crate) fn copy_asset(metadata: &Metadata, path: &Path) -> Result<(), Error> {
+    if path.file_name() == Some (OsStr::new(CONFIG_FILENAME))
+        || is_layout(metadata, path)
+        || is_partial(metadata, path)
+    {
+        return Ok (());
+    }
+
+    let destination = metadata.get_output_path(path);
+
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    if let (Some (src), Some (dst)) = (modified(path), modified(&destination)) {
+        if dst >= src {
+            return Ok (());
+        }
+    }
+
+    if let Some (parent) = destination.parent() {
+        if let Err (e) = fs::create_dir_all(parent) {
+            return Err (Error::CannotCreateDir {
+                path: parent.to_path_buf(),
+                source: e,
+            });
         }
     }
+
+    match fs::copy(path, &destination) {
+        Ok (_) => Ok (()),
+        Err (e) => Err (Error::CannotCopyFile {
+            path: path.to_path_buf(),
+            source: e,
+        }),
+    }
+}
+
+/// Prints a summary table of a build, returning an error holding the
+/// errors of every page or other file that failed, if any did.
+///
+/// Only pages are counted as pages; other files, such as assets that
+/// could not be copied, are counted separately.
+fn summarize(metadata: &Metadata, results: Vec<(PathBuf, Result<Status, Error>)>) -> Result<(), Error> {
+    let total = results.iter()
+        .filter(|(p, _)| is_page(metadata, p))
+        .count();
+    let count = |status: Status| results.iter()
+        .filter(|(_, r)| matches!(r, Ok (s) if *s == status))
+        .count();
+    let failed = results.iter()
+        .filter(|(p, r)| r.is_err() && is_page(metadata, p))
+        .count();
+    let files = results.iter()
+        .filter(|(p, r)| r.is_err() && !is_page(metadata, p))
+        .count();
+
+    println!();
+    println!("Build Summary");
+    println!("    {:<10}{:>6}  FILE", "STATUS", "ERRORS");
+    for (path, result) in &results {
+        let (status, count) = match result {
+            Ok (Status::Compiled) => ("ok", 0),
+            Ok (Status::Unchanged) => ("unchanged", 0),
+            Ok (Status::Draft) => ("draft", 0),
+            Err (e) => ("FAILED", e.count()),
+        };
+        println!("    {:<10}{:>6}  {}", status, count, path.display());
+    }
+    println!(
+        "{} pages, {} unchanged, {} drafts, {} failed",
+        total,
+        count(Status::Unchanged),
+        count(Status::Draft),
+        failed,
+    );
+    if files > 0 {
+        println!("{} other files could not be copied", files);
+    }
+
+    // The errors have been printed already, but are kept
+    // together, in order, for callers of the library
+    if failed + files > 0 {
+        let errors = results.into_iter()
+            .flat_map(|(_, r)| match r {
+                Err (Error::Multiple (v)) => v,
+                Err (e) => vec![e],
+                Ok (_) => Vec::new(),
+            })
+            .collect();
+        Err (Error::BuildFailed {
+            failed,
+            total,
+            files,
+            source: Box::new(Error::Multiple (errors)),
+        })
+    } else {
+        Ok (())
+    }
 }
 
-pub fn help() {
+/// Prints the help menu for a command.
+pub fn help(command_option: CommandOption) -> Result<(), Error> {
     println!("Blog Builder");
     println!("Version {}", VERSION);
     println!();
-    println!("{}", HELP);
 
-    process::exit(0);
-}
\ No newline at end of file
+    match command_option {
+        CommandOption::Compile => println!("{}", HELP_COMPILE),
+        CommandOption::Build => println!("{}", HELP_BUILD),
+        CommandOption::Serve => println!("{}", HELP_SERVE),
+        CommandOption::Watch => println!("{}", HELP_WATCH),
+        CommandOption::Help => println!("{}", HELP),
+    }
+
+    Ok (())
+}
+
+#[test]
+fn build_with_plugins() {
+    struct Badge;
+
+    impl ControlHandler for Badge {
+        fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Result<Expression, Error> {
+            let text = parser.text_argument(tokenizer)?;
+            Ok (Expression::Extension (Extension::new("badge", vec![text], Vec::new())))
+        }
+    }
+
+    impl Renderer for Badge {
+        fn render(&self, extension: &Extension, _: &[String]) -> Result<String, Error> {
+            Ok (format!("<span class=\"badge\">{}</span>", escape::text(&extension.get_arguments()[0])))
+        }
+    }
+
+    let site = test_dir::TestDir::new("build-with-plugins");
+    site.write("a.txt", "~ Status: \\badge{stable}");
+    site.write("posts/b.txt", "~ Also \\badge{new}");
+
+    let args = ["build".to_string(), site.path().display().to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+
+    // Without the plugin, the control sequence is unknown
+    assert!(matches!(build(&metadata), Err (Error::BuildFailed { failed: 2, total: 2, .. })));
+
+    let parser = Parser::builder().control("badge", Badge);
+    let mut renderers = Renderers::new();
+    renderers.insert("badge", Badge);
+    build_with(&metadata, &parser, &renderers).unwrap();
+
+    let a = fs::read_to_string(site.join("a.html")).unwrap();
+    assert!(a.contains("<p>Status: <span class=\"badge\">stable</span></p>"));
+    let b = fs::read_to_string(site.join("posts/b.html")).unwrap();
+    assert!(b.contains("<p>Also <span class=\"badge\">new</span></p>"));
+
+    // Extensions cannot be compiled without their renderers
+    compile_with(&metadata.with_input(site.join("a.txt")), &parser, &Renderers::new()).unwrap_err();
+}
+
+#[test]
+fn parallel_build() {
+    let site = test_dir::TestDir::new("parallel-build");
+    for (i, name) in ["a", "b", "c", "d", "e", "f", "g", "h"].iter().enumerate() {
+        // Every third page fails, the last with two errors
+        let source = match i % 3 {
+            1 if i == 7 => "~ \\unknown{}\n~ \\missing{}",
+            1 => "~ \\unknown{}",
+            _ => "~ Fine",
+        };
+        site.write(&format!("{}/{}.txt", name, name), source);
+    }
+
+    let args = [
+        "build".to_string(),
+        site.path().display().to_string(),
+        "--jobs".to_string(),
+        "4".to_string(),
+    ];
+    let metadata = Metadata::from_args(&args).unwrap();
+
+    let (failed, total, errors) = match build(&metadata) {
+        Err (Error::BuildFailed { failed, total, source, .. }) => match *source {
+            Error::Multiple (errors) => (failed, total, errors),
+            e => panic!("errors are not collected: {}", e),
+        },
+        r => panic!("build did not fail: {:?}", r.map_err(|e| e.to_string())),
+    };
+    assert_eq!((failed, total), (3, 8));
+
+    // Errors are collected in the order of the source tree,
+    // however the pages were scheduled
+    let paths = errors.iter()
+        .map(|e| match e {
+            Error::InFile { path, .. } => path.strip_prefix(site.path()).unwrap().to_path_buf(),
+            e => panic!("error is not located: {}", e),
+        })
+        .collect::<Vec<PathBuf>>();
+    assert_eq!(paths, ["b/b.txt", "e/e.txt", "h/h.txt", "h/h.txt"].map(PathBuf::from));
+}
+
+#[test]
+fn build_into_out() {
+    let site = test_dir::TestDir::new("build-into-out");
+    site.write("a.txt", "~ Hello");
+    site.write("media/x.png", "png");
+    site.write(".well-known/security.txt", "Contact: mailto:a@b.c");
+    site.write(".htaccess", "Options -Indexes");
+    site.write(".git/HEAD", "ref: refs/heads/main");
+
+    let out = site.join("public");
+    let sources = || WalkDir::new(site.path())
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.path().starts_with(&out))
+        .map(|e| (e.path().to_path_buf(), fs::read(e.path()).ok()))
+        .collect::<Vec<_>>();
+    let before = sources();
+
+    let args = [
+        "build".to_string(),
+        site.path().display().to_string(),
+        "--out".to_string(),
+        out.display().to_string(),
+        "--style".to_string(),
+        "tech".to_string(),
+    ];
+    let metadata = Metadata::from_args(&args).unwrap();
+
+    // Reading the arguments writes nothing
+    assert!(!out.exists());
+    assert_eq!(sources(), before);
+
+    build(&metadata).unwrap();
+    for file in ["a.html", "style.css", "media/x.png", ".well-known/security.txt", ".htaccess", CACHE_FILENAME] {
+        assert!(out.join(file).is_file(), "{} was not written", file);
+    }
+    assert!(!out.join(".well-known/security.html").exists());
+    assert!(!out.join(".git").exists());
+    assert_eq!(sources(), before);
+
+    // Files that cannot be copied are not counted as pages
+    fs::remove_dir_all(out.join("media")).unwrap();
+    fs::write(out.join("media"), "").unwrap();
+    let err = build(&metadata).unwrap_err();
+    assert!(matches!(err, Error::BuildFailed { failed: 0, total: 1, files: 1, .. }));
+    assert_eq!(err.to_string(), "[ERROR] build failed: 0 of 1 pages could not be compiled, and 1 other files could not be copied");
+}
+
+#[test]
+fn draft_pages() {
+    let site = test_dir::TestDir::new("draft-pages");
+    let page = site.write("a.txt", "~ Hello");
+
+    let args = ["build".to_string(), site.path().display().to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+    build(&metadata).unwrap();
+    assert!(site.join("a.html").is_file());
+
+    // A page that becomes a draft is unpublished
+    site.write("a.txt", "+++\ndraft = true\n+++\n~ Hello");
+    build(&metadata).unwrap();
+    assert!(!site.join("a.html").exists());
+
+    let cache = Cache::load(&metadata, &Parser::builder(), &Renderers::new());
+    assert!(!cache.is_fresh(&metadata, &page, &History::new(site.path())));
+}
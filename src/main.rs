@@ -6,15 +6,24 @@ use utils::{
     compile,
     help,
     build,
+    serve,
+    watch,
 };
 
 fn main() {
-    let metadata = Metadata::get();
+    let metadata = Metadata::get().unwrap_or_else(|e| e.throw());
     let command_option = metadata.get_command_option();
 
-    match command_option {
+    let result = match command_option {
+        _ if metadata.is_help() => help(command_option),
         CommandOption::Compile => compile(&metadata),
         CommandOption::Build => build(&metadata),
-        CommandOption::Help => help(),
+        CommandOption::Serve => serve(&metadata),
+        CommandOption::Watch => watch(&metadata),
+        CommandOption::Help => help(command_option),
+    };
+
+    if let Err (e) = result {
+        e.throw();
     }
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
 //! Alphanumeric parselet.
 
+use crate::Error;
+
 use super::{
     Expression,
     Parser,
@@ -12,7 +14,7 @@ use super::{
 pub struct AlphanumericParselet;
 
 impl PrefixParselet for AlphanumericParselet {
-    fn parse(&self, _: &Parser, _: &mut Tokenizer, token: &Token) -> Expression {
-        Expression::Alphanumeric (token.get_value())
+    fn parse(&self, _: &Parser, _: &mut Tokenizer, token: &Token) -> Result<Expression, Error> {
+        Ok (Expression::Alphanumeric (token.get_value()))
     }
 }
\ No newline at end of file
@@ -3,9 +3,13 @@
 use crate::Error;
 
 use super::{
+    macros::{self, MAX_PARAMETERS},
     Expression,
+    Macro,
     Parser,
     PrefixParselet,
+    Span,
+    TableParselet,
     Token,
     TokenClass,
     Tokenizer,
@@ -14,19 +18,43 @@ use super::{
 /// Parselet for control sequences.
 pub struct ControlParselet;
 
+impl ControlParselet {
+    /// Finds the macro invoked by a control sequence, along with the span
+    /// of its name and the text that follows it.
+    ///
+    /// The name of a macro ends at whitespace, so that text may follow a
+    /// macro without arguments, as in `\site today`.
+    fn split_macro(parser: &Parser, command: &Token) -> Option<(Macro, Span, String)> {
+        let value = command.get_value();
+        let start = value.len() - value.trim_start().len();
+        let end = value[start..].find(char::is_whitespace).map_or(value.len(), |i| start + i);
+
+        let definition = parser.get_macro(&value[start..end])?;
+        let span = command.get_span();
+        let span = Span {
+            offset: span.offset + start,
+            length: end - start,
+            column: span.column + value[..start].chars().count(),
+            ..span
+        };
+
+        Some ((definition, span, value[end..].to_string()))
+    }
+}
+
 impl PrefixParselet for ControlParselet {
-    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Expression {
-        let command = tokenizer.discard(TokenClass::Alphanumeric);
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Result<Expression, Error> {
+        let command = tokenizer.expect(TokenClass::Alphanumeric)?;
 
-        match command.as_str() {
+        let expression = match command.get_value().trim() {
             "href" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let name = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let name = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let href = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let href = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Hyperlink {
                     name,
@@ -34,31 +62,31 @@ impl PrefixParselet for ControlParselet {
                 }
             },
             "code" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let language = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let language = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let code = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let code = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Code {
                     language,
-                    code,
+                    code: parser.resolve(&code).display().to_string(),
                 }
             },
             "tile" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let name = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let name = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let img = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let img = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let href = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let href = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Tile {
                     name,
@@ -67,21 +95,21 @@ impl PrefixParselet for ControlParselet {
                 }
             },
             "tiledesc" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let name = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let name = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let desc = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let desc = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let img = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let img = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let href = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let href = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::TileDesc {
                     name,
@@ -91,17 +119,17 @@ impl PrefixParselet for ControlParselet {
                 }
             },
             "img" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let src = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let src = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let alt = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let alt = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let scale = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let scale = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Image {
                     img: src,
@@ -110,13 +138,13 @@ impl PrefixParselet for ControlParselet {
                 }
             },
             "floating" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let src = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let src = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let alt = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let alt = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::FloatingImage {
                     img: src,
@@ -124,27 +152,27 @@ impl PrefixParselet for ControlParselet {
                 }
             },
             "bold" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Bold (inside)
             },
             "it" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Italic (inside)
             },
             "block" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let quote = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let quote = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
-                tokenizer.discard(TokenClass::OpenCurly);
-                let citation = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let citation = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::BlockQuote {
                     quote,
@@ -152,65 +180,182 @@ impl PrefixParselet for ControlParselet {
                 }
             },
             "header" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Header (inside)
             },
             "footer" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Footer (inside)
             },
             "footnote" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Footnote (inside)
             },
             "footnotes" => Expression::Footnotes,
             "topblock" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Topblock (inside)
             },
             "subtitle" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Subtitle (inside)
             },
             "subsubtitle" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Subsubtitle (inside)
             },
             "menu" => Expression::Menu,
             "date" => Expression::Date,
             "tiles" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence());
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let inside = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Tiles (inside)
             },
             "pagename" => {
-                tokenizer.discard(TokenClass::OpenCurly);
-                let name = tokenizer.discard(TokenClass::Alphanumeric);
-                tokenizer.discard(TokenClass::CloseCurly);
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let name = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
 
                 Expression::Pagename (name)
             },
-            _ => Error::UnrecognizedControlSequence.throw(),
-        }
+            "raw" => {
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let html = tokenizer.discard_source()?;
+
+                Expression::Raw (html)
+            },
+            "table" => {
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let file = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
+
+                // Column alignments are optional
+                let alignments = match tokenizer.peek() {
+                    Some (t) if t.get_class() == TokenClass::OpenCurly => {
+                        tokenizer.next();
+                        let letters = tokenizer.discard_text()?;
+                        tokenizer.discard(TokenClass::CloseCurly)?;
+
+                        TableParselet::parse_alignments(&letters)?
+                    },
+                    _ => Vec::new(),
+                };
+
+                let caption = TableParselet::parse_caption(parser, tokenizer)?;
+
+                TableParselet::read_csv(parser, &file, alignments, caption)?
+            },
+            "html" => {
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let file = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
+
+                Expression::HtmlFile (parser.resolve(&file).display().to_string())
+            },
+            "include" => {
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let file = tokenizer.discard_text()?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
+
+                parser.include(&command, &file)?
+            },
+            "def" => {
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let name = tokenizer.expect(TokenClass::Alphanumeric)?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
+
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let parameters = tokenizer.expect(TokenClass::Alphanumeric)?;
+                tokenizer.discard(TokenClass::CloseCurly)?;
+
+                tokenizer.discard(TokenClass::OpenCurly)?;
+                let body = tokenizer.discard_source()?;
+
+                let name_value = name.get_value().trim().to_string();
+                if !macros::is_valid_name(&name_value) {
+                    return Err (Error::InvalidMacro {
+                        name: name_value,
+                        span: name.get_span(),
+                        message: "names may only contain letters, digits, `-` and `_`".to_string(),
+                    });
+                }
+
+                let count = match parameters.get_value().trim().parse() {
+                    Ok (n) if n <= MAX_PARAMETERS => n,
+                    _ => return Err (Error::InvalidMacro {
+                        name: name_value,
+                        span: parameters.get_span(),
+                        message: format!("number of parameters must be from 0 to {}", MAX_PARAMETERS),
+                    }),
+                };
+
+                parser.define(&name_value, Macro::new(count, body));
+
+                Expression::Group (Vec::new())
+            },
+            // Built-in control sequences take precedence over added
+            // control sequences, which take precedence over macros
+            name => match (parser.get_control(name), Self::split_macro(parser, &command)) {
+                (Some (c), _) => c.parse(parser, tokenizer, &command)?,
+                (None, Some ((m, span, rest))) => {
+                    let name = name[..span.length].to_string();
+                    if !rest.trim().is_empty() && m.get_parameters() > 0 {
+                        return Err (Error::InvalidMacro {
+                            name,
+                            span,
+                            message: format!("expected {} arguments directly after its name", m.get_parameters()),
+                        });
+                    }
+
+                    let mut arguments = Vec::new();
+                    for _ in 0..m.get_parameters() {
+                        tokenizer.discard(TokenClass::OpenCurly)?;
+                        arguments.push(tokenizer.discard_source()?);
+                    }
+
+                    // A macro without arguments may be followed by `{}`,
+                    // so that text can follow it without a space
+                    let empty = |n| tokenizer.peek_nth(n).map(|t| t.get_class());
+                    if rest.trim().is_empty()
+                        && m.get_parameters() == 0
+                        && empty(0) == Some (TokenClass::OpenCurly)
+                        && empty(1) == Some (TokenClass::CloseCurly)
+                    {
+                        tokenizer.next();
+                        tokenizer.next();
+                    }
+
+                    let mut expansion = parser.parse_expansion(&name, span, m.expand(&arguments))?;
+                    if !rest.is_empty() {
+                        expansion.push(Expression::Alphanumeric (rest));
+                    }
+
+                    Expression::Group (expansion)
+                },
+                (None, None) => return Err (Error::UnrecognizedControlSequence (command)),
+            },
+        };
+
+        Ok (expression)
     }
 }
\ No newline at end of file
@@ -1,17 +1,49 @@
 //! Enumerates the types of expressions available in the Blog Builder.
 
 use std::{
-    fmt::{
-        Display,
-        Formatter,
-        Result,
-    },
     fs,
+    path::PathBuf,
+};
+
+use crate::{
+    emitter::Renderers,
+    escape,
+    Error,
 };
 
-use chrono::prelude::*;
+use super::Extension;
+
+/// Cells of a table row, each a sequence of expressions.
+pub type Row = Vec<Vec<Expression>>;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+/// Horizontal alignment of a table column.
+pub enum Alignment {
+    /// Aligned as the stylesheet decides.
+    #[default]
+    Default,
 
-use crate::Error;
+    /// Aligned to the left.
+    Left,
+
+    /// Centered.
+    Center,
+
+    /// Aligned to the right.
+    Right,
+}
+
+impl Alignment {
+    /// Gets the HTML attribute that applies this alignment to a cell.
+    fn attribute(&self) -> &'static str {
+        match self {
+            Self::Default => "",
+            Self::Left => " style=\"text-align: left;\"",
+            Self::Center => " style=\"text-align: center;\"",
+            Self::Right => " style=\"text-align: right;\"",
+        }
+    }
+}
 
 #[derive(PartialEq, Clone, Debug)]
 /// Types of expressions available to the Blog Builder.
@@ -37,7 +69,7 @@ pub enum Expression {
     /// Alphanumeric string.
     Alphanumeric (String),
     
-    /// Newline character equivalent to HTML `<br>`.
+    /// Newline character, kept as a line break in the HTML source.
     Newline,
 
     /// Bold text.
@@ -46,6 +78,36 @@ pub enum Expression {
     /// Italic text.
     Italic (Vec<Expression>),
 
+    /// Unordered list equivalent to HTML `ul`, holding the contents of each item.
+    UnorderedList (Vec<Vec<Expression>>),
+
+    /// Ordered list equivalent to HTML `ol`.
+    OrderedList {
+        /// Number of the first item.
+        start: usize,
+
+        /// Contents of each item.
+        items: Vec<Vec<Expression>>,
+    },
+
+    /// Table equivalent to HTML `table`.
+    Table {
+        /// Alignment of each column.
+        alignments: Vec<Alignment>,
+
+        /// Rows of column headings.
+        header: Vec<Row>,
+
+        /// Rows of data.
+        body: Vec<Row>,
+
+        /// Table caption.
+        caption: Vec<Expression>,
+
+        /// CSV file the table was read from, if any.
+        file: Option<String>,
+    },
+
     /// Block quote.
     BlockQuote {
         /// Quote content.
@@ -69,7 +131,7 @@ pub enum Expression {
         /// Language of the source code.
         language: String,
 
-        /// Source code.
+        /// Path to the source code file.
         code: String,
     },
 
@@ -139,7 +201,7 @@ pub enum Expression {
     /// Web menu.
     Menu,
 
-    /// "Last Updated" date.
+    /// "Last Updated" date, filled in by the emitter.
     Date,
 
     /// Collection of tiles.
@@ -147,280 +209,383 @@ pub enum Expression {
 
     /// Webpage name.
     Pagename (String),
+
+    /// Inline raw HTML, emitted without escaping.
+    Raw (String),
+
+    /// Block of raw HTML read from a file, emitted without escaping.
+    HtmlFile (String),
+
+    /// Expressions produced together, such as by a macro, which the
+    /// parser splices into the surrounding expressions.
+    Group (Vec<Expression>),
+
+    /// Contents of another source file included in the page.
+    Include {
+        /// Path to the included file.
+        file: String,
+
+        /// Expressions parsed from the included file.
+        expressions: Vec<Expression>,
+    },
+
+    /// Expression produced by a control sequence added to the parser,
+    /// rendered by the renderer registered for it.
+    Extension (Extension),
 }
 
-impl Display for Expression {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+impl Expression {
+    /// Removes leading and trailing whitespace from a sequence of expressions.
+    pub fn trim(expressions: &mut Vec<Expression>) {
+        while let Some (e) = expressions.first() {
+            match e {
+                Expression::Newline => (),
+                Expression::Alphanumeric (s) if s.trim().is_empty() => (),
+                _ => break,
+            }
+            expressions.remove(0);
+        }
+
+        while let Some (e) = expressions.last() {
+            match e {
+                Expression::Newline => (),
+                Expression::Alphanumeric (s) if s.trim().is_empty() => (),
+                _ => break,
+            }
+            expressions.pop();
+        }
+
+        if let Some (Expression::Alphanumeric (s)) = expressions.first_mut() {
+            *s = s.trim_start().to_string();
+        }
+
+        if let Some (Expression::Alphanumeric (s)) = expressions.last_mut() {
+            *s = s.trim_end().to_string();
+        }
+    }
+
+    /// Checks whether this expression renders as an HTML block element,
+    /// which cannot be nested within a paragraph.
+    pub fn is_block(&self) -> bool {
         use Expression::*;
-        
-        let value = match self {
-            Title (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h2>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h2>");
-                inside
-            },
-            Subtitle (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h5>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h5>");
-                inside
-            },
-            Subsubtitle (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h6>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h6>");
-                inside
-            },
-            Heading (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h3>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h3>");
-                inside
-            },
-            Subheading (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h4>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h4>");
-                inside
-            },
-            Alphanumeric (s) => {
-                s.to_owned()
-            },
-            Paragraph (v) => {
-                let mut inside = String::new();
-                inside.push_str("<p>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
+
+        match self {
+            Extension (e) => e.is_block(),
+            Include { expressions, .. } => expressions.iter().any(|e| e.is_block()),
+            _ => !matches!(
+                self,
+                Alphanumeric (_) | Newline | Bold (_) | Italic (_) | Hyperlink { .. }
+                    | Image { .. } | FloatingImage { .. } | Footnote (_) | Pagename (_) | Raw (_)
+            ),
+        }
+    }
+
+    /// Lists the files that this expression and its children read when rendered.
+    pub fn get_dependencies(&self) -> Vec<PathBuf> {
+        use Expression::*;
+
+        let children = match self {
+            Code { code, .. } => return vec![PathBuf::from(code)],
+            Table {
+                header,
+                body,
+                caption,
+                file,
+                ..
+            } => return file.iter()
+                .map(PathBuf::from)
+                .chain(
+                    header.iter()
+                        .chain(body)
+                        .flatten()
+                        .flatten()
+                        .chain(caption)
+                        .flat_map(|e| e.get_dependencies())
+                )
+                .collect(),
+            HtmlFile (f) => return vec![PathBuf::from(f)],
+            Include {
+                file,
+                expressions,
+            } => return std::iter::once(PathBuf::from(file))
+                .chain(expressions.iter().flat_map(|e| e.get_dependencies()))
+                .collect(),
+            Title (v) | Heading (v) | Subheading (v) | Subtitle (v) | Subsubtitle (v)
+                | Paragraph (v) | Bold (v) | Italic (v) | Header (v) | Footer (v)
+                | Footnote (v) | Topblock (v) | Tiles (v) | Group (v) => v.iter().collect(),
+            UnorderedList (items) | OrderedList { items, .. } => items.iter().flatten().collect(),
+            BlockQuote {
+                quote,
+                citation,
+            } => quote.iter().chain(citation).collect(),
+            Hyperlink { name, .. } | Tile { name, .. } => name.iter().collect(),
+            TileDesc {
+                name,
+                desc,
+                ..
+            } => name.iter().chain(desc).collect(),
+            Extension (e) => e.get_children().iter().flatten().collect(),
+            _ => Vec::<&Expression>::new(),
+        };
+
+        children.into_iter()
+            .flat_map(|e| e.get_dependencies())
+            .collect()
+    }
+
+    /// Lists the child expressions of this expression, so that they can be changed.
+    pub(crate) fn get_children_mut(&mut self) -> Vec<&mut Expression> {
+        use Expression::*;
+
+        match self {
+            Title (v) | Heading (v) | Subheading (v) | Subtitle (v) | Subsubtitle (v)
+                | Paragraph (v) | Bold (v) | Italic (v) | Header (v) | Footer (v)
+                | Footnote (v) | Topblock (v) | Tiles (v) | Group (v)
+                | Include { expressions: v, .. } => v.iter_mut().collect(),
+            UnorderedList (items) | OrderedList { items, .. } => items.iter_mut().flatten().collect(),
+            Table {
+                header,
+                body,
+                caption,
+                ..
+            } => header.iter_mut()
+                .chain(body)
+                .flatten()
+                .flatten()
+                .chain(caption)
+                .collect(),
+            BlockQuote {
+                quote,
+                citation,
+            } => quote.iter_mut().chain(citation).collect(),
+            Hyperlink { name, .. } | Tile { name, .. } => name.iter_mut().collect(),
+            TileDesc {
+                name,
+                desc,
+                ..
+            } => name.iter_mut().chain(desc).collect(),
+            Extension (e) => e.get_children_mut().iter_mut().flatten().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Renders the contents of a paragraph.
+    /// 
+    /// Runs of inline content are wrapped in `<p>` elements, while block
+    /// elements are placed between them so that the paragraph stays
+    /// well-formed.
+    fn render_paragraph(expressions: &[Expression], renderers: &Renderers) -> Result<String, Error> {
+        let mut blocks = Vec::new();
+        let mut inline = Vec::new();
+
+        for expr in expressions {
+            if expr.is_block() {
+                Self::trim(&mut inline);
+                if !inline.is_empty() {
+                    blocks.push(format!("<p>{}</p>", Self::render_all_with(&inline, renderers)?));
                 }
-                inside
-            },
-            Newline => {
-                String::new()
-            },
-            Bold (v) => {
-                let mut inside = String::new();
-                inside.push_str(" <strong>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
+                inline.clear();
+                blocks.push(expr.render_with(renderers)?);
+            } else {
+                inline.push(expr.to_owned());
+            }
+        }
+
+        Self::trim(&mut inline);
+        if !inline.is_empty() {
+            blocks.push(format!("<p>{}</p>", Self::render_all_with(&inline, renderers)?));
+        }
+
+        Ok (blocks.join("\n"))
+    }
+
+    /// Renders the items of a list.
+    fn render_items(items: &[Vec<Expression>], renderers: &Renderers) -> Result<String, Error> {
+        let mut rendered = Vec::new();
+        for item in items {
+            rendered.push(format!("<li>{}</li>", Self::render_all_with(item, renderers)?));
+        }
+        Ok (rendered.join("\n"))
+    }
+
+    /// Renders the rows of a table, using `cell` as the name of each cell's element.
+    fn render_rows(rows: &[Row], alignments: &[Alignment], cell: &str, renderers: &Renderers) -> Result<String, Error> {
+        let mut rendered = Vec::new();
+        for row in rows {
+            let mut cells = String::new();
+            for (i, c) in row.iter().enumerate() {
+                let alignment = alignments.get(i).copied().unwrap_or_default();
+                cells.push_str(&format!("<{}{}>{}</{}>", cell, alignment.attribute(), Self::render_all_with(c, renderers)?, cell));
+            }
+            rendered.push(format!("<tr>{}</tr>", cells));
+        }
+        Ok (rendered.join("\n"))
+    }
+
+    /// Renders a sequence of expressions into HTML.
+    pub fn render_all(expressions: &[Expression]) -> Result<String, Error> {
+        Self::render_all_with(expressions, &Renderers::new())
+    }
+
+    /// Renders a sequence of expressions into HTML, rendering extensions
+    /// with the given renderers.
+    pub fn render_all_with(expressions: &[Expression], renderers: &Renderers) -> Result<String, Error> {
+        let mut output = String::new();
+        for expr in expressions {
+            output.push_str(&expr.render_with(renderers)?);
+        }
+        Ok (output)
+    }
+
+    /// Renders this expression into HTML.
+    pub fn render(&self) -> Result<String, Error> {
+        self.render_with(&Renderers::new())
+    }
+
+    /// Renders this expression into HTML, rendering extensions with the
+    /// given renderers.
+    pub fn render_with(&self, renderers: &Renderers) -> Result<String, Error> {
+        use Expression::*;
+
+        let value = match self {
+            Title (v) => format!("<h2>{}</h2>", Self::render_all_with(v, renderers)?),
+            Subtitle (v) => format!("<h5>{}</h5>", Self::render_all_with(v, renderers)?),
+            Subsubtitle (v) => format!("<h6>{}</h6>", Self::render_all_with(v, renderers)?),
+            Heading (v) => format!("<h3>{}</h3>", Self::render_all_with(v, renderers)?),
+            Subheading (v) => format!("<h4>{}</h4>", Self::render_all_with(v, renderers)?),
+            Alphanumeric (s) => escape::text(s),
+            Paragraph (v) => Self::render_paragraph(v, renderers)?,
+            Newline => "\n".to_string(),
+            Bold (v) => format!("<strong>{}</strong>", Self::render_all_with(v, renderers)?),
+            Italic (v) => format!("<em>{}</em>", Self::render_all_with(v, renderers)?),
+            UnorderedList (items) => format!("<ul>\n{}\n</ul>", Self::render_items(items, renderers)?),
+            OrderedList {
+                start: 1,
+                items,
+            } => format!("<ol>\n{}\n</ol>", Self::render_items(items, renderers)?),
+            OrderedList {
+                start,
+                items,
+            } => format!("<ol start=\"{}\">\n{}\n</ol>", start, Self::render_items(items, renderers)?),
+            Table {
+                alignments,
+                header,
+                body,
+                caption,
+                ..
+            } => {
+                let mut table = vec!["<table>".to_string()];
+                if !caption.is_empty() {
+                    table.push(format!("<caption>{}</caption>", Self::render_all_with(caption, renderers)?));
                 }
-                inside.push_str("</strong> ");
-                inside
-            },
-            Italic (v) => {
-                let mut inside = String::new();
-                inside.push_str(" <em>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
+                if !header.is_empty() {
+                    table.push(format!("<thead>\n{}\n</thead>", Self::render_rows(header, alignments, "th", renderers)?));
                 }
-                inside.push_str("</em> ");
-                inside
+                table.push(format!("<tbody>\n{}\n</tbody>", Self::render_rows(body, alignments, "td", renderers)?));
+                table.push("</table>".to_string());
+                table.join("\n")
             },
             BlockQuote {
                 quote: q,
                 citation: c,
-            } => {
-                let mut inside = String::new();
-                inside.push_str(" <p class=\"block\">");
-                for expr in q {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</p><p class=\"citation\">~ ");
-                for expr in c {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</p>");
-                inside
-            },
+            } => format!(
+                " <p class=\"block\">{}</p><p class=\"citation\">~ {}</p>",
+                Self::render_all_with(q, renderers)?,
+                Self::render_all_with(c, renderers)?,
+            ),
             Hyperlink {
                 name: n,
                 href: h,
-            } => {
-                let mut inside = String::new();
-                inside.push_str(" <a href=\"");
-                inside.push_str(&h);
-                inside.push_str("\">");
-                for expr in n {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</a> ");
-                inside
-            },
+            } => format!(
+                "<a href=\"{}\">{}</a>",
+                escape::url(h),
+                Self::render_all_with(n, renderers)?,
+            ),
             Code {
                 language: l,
                 code: f,
             } => {
-                let mut code = match fs::read_to_string(f) {
+                let code = match fs::read_to_string(f) {
                     Ok (c) => c,
-                    Err (_) => Error::CannotOpenFile.throw(),
+                    Err (e) => return Err (Error::CannotOpenFile {
+                        path: PathBuf::from(f),
+                        source: e,
+                    }),
                 };
 
-                // Raw < and > in HTML will cause the browser
-                // to interpret this as a tag
-                code = code.replace("<", "&lt;");
-                code = code.replace(">", "&gt;");
-
                 format!(
                     "<pre><code class=\"language-{}\">{}</code></pre>",
-                    l,
-                    code,
+                    escape::attribute(l),
+                    escape::text(&code),
                 )
             },
             Tile {
                 name: n,
                 img: i,
                 href: h,
-            } => {
-                let mut inside = format!(
-                    " <div class=\"tile\" onclick=\"window.location=\'{}\';\" style=\"background-image: url('{}'); cursor: pointer; background-position: center;\"><div>",
-                    &h,
-                    &i,
-                );
-                for expr in n {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</div></div> ");
-                inside
-            },
+            } => format!(
+                " <div class=\"tile\" onclick=\"window.location=\'{}\';\" style=\"background-image: url('{}'); cursor: pointer; background-position: center;\"><div>{}</div></div> ",
+                escape::script_url(h),
+                escape::style_url(i),
+                Self::render_all_with(n, renderers)?,
+            ),
             TileDesc {
                 name: n,
                 desc: d,
                 img: i,
                 href: h,
-            } => {
-                let mut inside = format!(
-                    " <div class=\"tile\" onclick=\"window.location=\'{}\';\" style=\"background-image: url('{}'); cursor: pointer; background-position: center;\"><div>",
-                    &h,
-                    &i,
-                );
-                for expr in n {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</div><br><div class=\"desc\">");
-                for expr in d {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</div></div> ");
-                inside
-            },
+            } => format!(
+                " <div class=\"tile\" onclick=\"window.location=\'{}\';\" style=\"background-image: url('{}'); cursor: pointer; background-position: center;\"><div>{}</div><br><div class=\"desc\">{}</div></div> ",
+                escape::script_url(h),
+                escape::style_url(i),
+                Self::render_all_with(n, renderers)?,
+                Self::render_all_with(d, renderers)?,
+            ),
             Image {
                 img: i,
                 alt: a,
                 scale: s,
             } => format!(
-                " <img src=\"{}\" style=\"height: {}px;\" alt=\"{}\"> ",
-                i,
-                s,
-                a,
+                "<img src=\"{}\" style=\"height: {}px;\" alt=\"{}\">",
+                escape::url(i),
+                escape::number(s),
+                escape::attribute(a),
             ),
             FloatingImage {
                 img: i,
                 alt: a,
             } => format!(
-                " <img src=\"{}\" class=\"floating\" alt=\"{}\"> ",
-                i,
-                a,
+                "<img src=\"{}\" class=\"floating\" alt=\"{}\">",
+                escape::url(i),
+                escape::attribute(a),
             ),
-            Header (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h1>");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h1>");
-                inside
-            },
-            Footer (v) => {
-                let mut inside = String::new();
-                inside.push_str("<h6 class=\"footer\">");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("</h6>");
-                inside
-            },
-            Footnote (v) => {
-                let mut inside = String::new();
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                format!(
-                    "<footnote>{}</footnote>",
-                    &inside,
-                )
-            },
-            Footnotes => {
-                "<footnotes>".to_string()
-            }
-            Topblock (v) => {
-                let mut inside = String::new();
-                inside.push_str("<div class=\"topblock\">");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
+            Header (v) => format!("<h1>{}</h1>", Self::render_all_with(v, renderers)?),
+            Footer (v) => format!("<h6 class=\"footer\">{}</h6>", Self::render_all_with(v, renderers)?),
+            Footnote (v) => format!("<footnote>{}</footnote>", Self::render_all_with(v, renderers)?),
+            Footnotes => "<footnotes>".to_string(),
+            Topblock (v) => format!("<div class=\"topblock\">{}</div>", Self::render_all_with(v, renderers)?),
+            Menu => "<menu>".to_string(),
+            Date => "<date>".to_string(),
+            Tiles (v) => format!("<div class=\"tiles\">\n{}\n</div>", Self::render_all_with(v, renderers)?),
+            Pagename (_) => String::new(),
+            Raw (s) => s.to_owned(),
+            Group (v) | Include { expressions: v, .. } => Self::render_all_with(v, renderers)?,
+            Extension (e) => {
+                let mut children = Vec::new();
+                for child in e.get_children() {
+                    children.push(Self::render_all_with(child, renderers)?);
                 }
-                inside.push_str("</div>");
-                inside
-            }
-            Menu => {
-                "<menu>".to_string()
-            },
-            Date => {
-                let local: DateTime<Local> = Local::now();
-                let date = local.format("%A, %B %d, %Y").to_string();
-
-                format!(
-                    "<h6 class=\"last-updated-date\">Last Updated {}</h6>",
-                    date
-                )
-            },
-            Tiles (v) => {
-                let mut inside = String::new();
-                inside.push_str("<div class=\"tiles\">\n");
-                for expr in v {
-                    let string = format!("{}", expr);
-                    inside.push_str(&string);
-                }
-                inside.push_str("\n</div>");
-                inside
+                renderers.render(e, &children)?
             },
-            Pagename (_) => {
-                String::new()
+            HtmlFile (f) => match fs::read_to_string(f) {
+                Ok (h) => h,
+                Err (e) => return Err (Error::CannotOpenFile {
+                    path: PathBuf::from(f),
+                    source: e,
+                }),
             },
         };
 
-        write!(f, "{}", value)
+        Ok (value)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,97 @@
+//! Extensions to the Blog Builder parser module.
+//!
+//! Crates using the Blog Builder as a library may add their own control
+//! sequences by registering a handler for each with the parser:
+//!
+//! ```text
+//! let parser = Parser::builder()
+//!     .control("youtube", YouTube)
+//!     .build();
+//! ```
+//!
+//! A handler parses the arguments of its control sequence and usually
+//! returns an `Expression::Extension`, which is rendered by the renderer
+//! registered with the emitter under the same name.  Whole sites are
+//! built with both by `build_with`, `compile_with`, `watch_with` and
+//! `serve_with`.
+
+use crate::Error;
+
+use super::{
+    Expression,
+    Parser,
+    Token,
+    Tokenizer,
+};
+
+/// Handler for a control sequence added to the parser, which may be
+/// called from any of the threads compiling a site.
+pub trait ControlHandler: Send + Sync {
+    /// Parses the arguments of the control sequence, which the tokenizer
+    /// is positioned just after.  `command` is the name of the sequence,
+    /// which locates any errors.
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, command: &Token) -> Result<Expression, Error>;
+
+    /// Identifies this handler and the version of its output, so that
+    /// builds compile pages again when it changes.  Defaults to the name
+    /// of its type, so handlers whose output changes from one release
+    /// to the next should add their version number.
+    fn version(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Expression produced by a control sequence added to the parser.
+pub struct Extension {
+    /// Name of the extension, which selects its renderer.
+    name: String,
+
+    /// Arguments given as plain text, such as addresses.
+    arguments: Vec<String>,
+
+    /// Arguments parsed as expressions, such as captions.
+    children: Vec<Vec<Expression>>,
+
+    /// Whether the extension renders as an HTML block element.
+    block: bool,
+}
+
+impl Extension {
+    /// Constructs a new inline extension.
+    pub fn new(name: &str, arguments: Vec<String>, children: Vec<Vec<Expression>>) -> Self {
+        Self {
+            name: name.to_string(),
+            arguments,
+            children,
+            block: false,
+        }
+    }
+
+    /// Marks the extension as an HTML block element, which is placed
+    /// between paragraphs rather than within them.
+    pub fn block(mut self) -> Self {
+        self.block = true;
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_arguments(&self) -> &[String] {
+        &self.arguments
+    }
+
+    pub fn get_children(&self) -> &[Vec<Expression>] {
+        &self.children
+    }
+
+    pub(crate) fn get_children_mut(&mut self) -> &mut [Vec<Expression>] {
+        &mut self.children
+    }
+
+    pub fn is_block(&self) -> bool {
+        self.block
+    }
+}
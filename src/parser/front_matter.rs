@@ -0,0 +1,240 @@
+//! Front matter for the Blog Builder parser module.
+//!
+//! Front matter is an optional block of TOML at the very top of a page,
+//! between two lines containing only `+++`:
+//!
+//! ```text
+//! +++
+//! title = "My Post"
+//! date = 2024-01-31
+//! tags = ["rust", "web"]
+//! +++
+//! ~ The page itself starts here.
+//! ```
+
+use chrono::NaiveDate;
+
+use toml::{
+    Table,
+    Value,
+};
+
+use crate::{
+    emitter::{
+        boolean,
+        string,
+        unknown_key,
+    },
+    Error,
+};
+
+use super::Span;
+
+/// Line that opens and closes the front matter.
+const DELIMITER: &str = "+++";
+
+/// Keys accepted in front matter.
+const KEYS: [&str; 9] = ["title", "description", "author", "date", "updated", "tags", "draft", "template", "slug"];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Metadata for a single page, read from its front matter.
+pub struct PageMeta {
+    /// Page title, which takes precedence over `\pagename`.
+    pub title: Option<String>,
+
+    /// Short description of the page, for search engines.
+    pub description: Option<String>,
+
+    /// Author of the page.
+    pub author: Option<String>,
+
+    /// Date on which the page was published.
+    pub date: Option<NaiveDate>,
+
+    /// Date on which the page was last updated.
+    pub updated: Option<NaiveDate>,
+
+    /// Tags describing the page.
+    pub tags: Vec<String>,
+
+    /// Whether the page is a draft, which is left out of builds.
+    pub draft: bool,
+
+    /// Name of the template used to lay out the page.
+    pub template: Option<String>,
+
+    /// File name of the page's output, without its extension.
+    pub slug: Option<String>,
+}
+
+impl PageMeta {
+    /// Splits the front matter from the start of a page.
+    ///
+    /// Returns the metadata and the position in the source at which the
+    /// rest of the page begins.  Pages without front matter have default
+    /// metadata and begin at the start of the source.
+    pub fn split(source: &str) -> Result<(Self, Span), Error> {
+        let start = Span::locate(source, 0, 0);
+
+        let first_line = source.lines().next().unwrap_or_default();
+        if first_line.trim_end() != DELIMITER {
+            return Ok ((Self::default(), start));
+        }
+
+        // Find the closing delimiter, which must be on a line of its own
+        let contents_start = source.find('\n').map_or(source.len(), |i| i + 1);
+        let mut offset = contents_start;
+        let mut contents_end = None;
+        for line in source[contents_start..].split_inclusive('\n') {
+            if line.trim_end() == DELIMITER {
+                contents_end = Some ((offset, offset + line.len()));
+                break;
+            }
+            offset += line.len();
+        }
+
+        let (contents_end, body_start) = match contents_end {
+            Some (e) => e,
+            None => return Err (Error::InvalidFrontMatter {
+                span: Span::locate(source, 0, DELIMITER.len()),
+                message: format!("front matter is not closed by a line containing `{}`", DELIMITER),
+            }),
+        };
+
+        let meta = Self::parse(source, contents_start, contents_end)?;
+
+        Ok ((meta, Span::locate(source, body_start, 0)))
+    }
+
+    /// Parses front matter, found between the given offsets in the source.
+    fn parse(source: &str, start: usize, end: usize) -> Result<Self, Error> {
+        let contents = &source[start..end];
+        let table = match contents.parse::<Table>() {
+            Ok (t) => t,
+            Err (e) => {
+                let range = e.span().unwrap_or(0..0);
+                return Err (Error::InvalidFrontMatter {
+                    span: Span::locate(source, start + range.start, range.len()),
+                    message: e.message().trim_end().to_string(),
+                });
+            },
+        };
+
+        let mut meta = Self::default();
+
+        for (key, value) in table {
+            let result = match key.as_str() {
+                "title" => string(&key, value).map(|v| meta.title = Some (v)),
+                "description" => string(&key, value).map(|v| meta.description = Some (v)),
+                "author" => string(&key, value).map(|v| meta.author = Some (v)),
+                "date" => date(&key, value).map(|v| meta.date = Some (v)),
+                "updated" => date(&key, value).map(|v| meta.updated = Some (v)),
+                "tags" => tags(&key, value).map(|v| meta.tags = v),
+                "draft" => boolean(&key, value).map(|v| meta.draft = v),
+                "template" => string(&key, value).map(|v| meta.template = Some (v)),
+                "slug" => slug(&key, value).map(|v| meta.slug = Some (v)),
+                _ => Err (unknown_key(&key, &KEYS)),
+            };
+
+            if let Err (message) = result {
+                return Err (Error::InvalidFrontMatter {
+                    span: key_span(source, start, end, &key),
+                    message,
+                });
+            }
+        }
+
+        Ok (meta)
+    }
+
+    /// Gets the title of the page, or a placeholder if it has none.
+    pub fn get_title(&self) -> String {
+        self.title.to_owned().unwrap_or("New Page".to_string())
+    }
+}
+
+/// Gets a date, written as a TOML date or a `YYYY-MM-DD` string,
+/// or returns an error.
+fn date(key: &str, value: Value) -> Result<NaiveDate, String> {
+    let invalid = || format!("key `{}` must be a date, such as 2024-01-31", key);
+
+    match value {
+        Value::Datetime (d) => match (d.date, d.time) {
+            (Some (date), None) => NaiveDate::from_ymd_opt(
+                date.year.into(),
+                date.month.into(),
+                date.day.into(),
+            ).ok_or_else(invalid),
+            _ => Err (invalid()),
+        },
+        Value::String (s) => NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(|_| invalid()),
+        _ => Err (invalid()),
+    }
+}
+
+/// Gets a list of tags, or returns an error.
+fn tags(key: &str, value: Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Array (a) => a.into_iter()
+            .map(|t| string(key, t).map_err(|_| format!("key `{}` must be a list of strings", key)))
+            .collect(),
+        v => Err (format!("key `{}` must be a list of strings, found {}", key, v.type_str())),
+    }
+}
+
+/// Gets a slug, which may only contain letters, digits, `-` and `_`,
+/// or returns an error.
+fn slug(key: &str, value: Value) -> Result<String, String> {
+    let slug = string(key, value)?;
+
+    if !slug.is_empty() && slug.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        Ok (slug)
+    } else {
+        Err (format!("key `{}` may only contain letters, digits, `-` and `_`", key))
+    }
+}
+
+/// Finds the line on which a key is set in the front matter, found
+/// between the given offsets in the source.
+fn key_span(source: &str, start: usize, end: usize, key: &str) -> Span {
+    let mut offset = start;
+    for line in source[start..end].split_inclusive('\n') {
+        let name = line.split('=').next().unwrap_or_default().trim();
+        if name == key || name.trim_matches('"') == key {
+            let indent = line.len() - line.trim_start().len();
+            return Span::locate(source, offset + indent, line.trim().len());
+        }
+        offset += line.len();
+    }
+
+    Span::locate(source, start, 0)
+}
+
+#[test]
+fn front_matter() {
+    let source = "+++\ntitle = \"Post\"\ndate = 2024-01-31\nupdated = \"2024-02-01\"\ntags = [\"a\", \"b\"]\ndraft = true\n+++\n~ Body";
+    let (meta, start) = PageMeta::split(source).unwrap();
+    assert_eq!(meta.title.as_deref(), Some ("Post"));
+    assert_eq!(meta.date, NaiveDate::from_ymd_opt(2024, 1, 31));
+    assert_eq!(meta.updated, NaiveDate::from_ymd_opt(2024, 2, 1));
+    assert_eq!(meta.tags, vec!["a", "b"]);
+    assert!(meta.draft);
+    assert_eq!(&source[start.offset..], "~ Body");
+    assert_eq!(start.line, 8);
+
+    let (meta, start) = PageMeta::split("~ No front matter").unwrap();
+    assert_eq!(meta, PageMeta::default());
+    assert_eq!(start.offset, 0);
+
+    let error = |source: &str| PageMeta::split(source)
+        .unwrap_err()
+        .in_file(std::path::Path::new("page.txt"), source)
+        .to_string();
+    assert_eq!(
+        error("+++\ntitle = \"Post\"\ndraft = \"yes\"\n+++\n"),
+        "[ERROR] page.txt:3:1: invalid front matter: key `draft` must be a boolean, found string\n  |\n3 | draft = \"yes\"\n  | ^^^^^^^^^^^^^",
+    );
+    assert!(error("+++\ntilte = \"Post\"\n+++\n").contains("unknown key `tilte` (did you mean `title`?)"));
+    assert!(error("+++\ntitle = \"Post\"\n").contains("page.txt:1:1: invalid front matter: front matter is not closed"));
+    assert!(error("+++\ntitle = \n+++\n").starts_with("[ERROR] page.txt:2:"));
+}
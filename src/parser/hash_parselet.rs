@@ -14,15 +14,16 @@ use super::{
 pub struct HashParselet;
 
 impl PrefixParselet for HashParselet {
-    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Expression {
-        let inside = parser.parse_tokens(tokenizer, token.get_precedence());
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Result<Expression, Error> {
+        let mut inside = parser.parse_tokens(tokenizer, token.get_precedence())?;
+        Expression::trim(&mut inside);
 
         match token.get_value().len() {
             0 => unreachable!(),
-            1 => Expression::Title (inside),
-            2 => Expression::Heading (inside),
-            3 => Expression::Subheading (inside),
-            _ => Error::TooManyHashes.throw_msg(&token.get_value()),
+            1 => Ok (Expression::Title (inside)),
+            2 => Ok (Expression::Heading (inside)),
+            3 => Ok (Expression::Subheading (inside)),
+            _ => Err (Error::TooManyHashes (token.to_owned())),
         }
     }
 }
\ No newline at end of file
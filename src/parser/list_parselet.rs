@@ -0,0 +1,79 @@
+//! List parselet.
+
+use crate::Error;
+
+use super::{
+    Expression,
+    Parser,
+    PrefixParselet,
+    Token,
+    TokenClass,
+    Tokenizer,
+};
+
+/// Parselet for ordered and unordered lists.
+///
+/// Each item is a line beginning with a marker, `-` for unordered lists
+/// or a number followed by `.` for ordered lists.  Items indented further
+/// than the item above them form a list nested within that item.
+pub struct ListParselet;
+
+impl ListParselet {
+    /// Checks whether a list item marker begins an ordered list.
+    fn is_ordered(token: &Token) -> bool {
+        token.get_value().ends_with('.')
+    }
+
+    /// Parses the contents of a list item, up to the end of its line.
+    fn parse_item(parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Result<Vec<Expression>, Error> {
+        let mut inside = parser.parse_tokens(tokenizer, token.get_precedence())?;
+        Expression::trim(&mut inside);
+
+        Ok (inside)
+    }
+}
+
+impl PrefixParselet for ListParselet {
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Result<Expression, Error> {
+        let ordered = Self::is_ordered(token);
+        let column = token.get_span().column;
+
+        let mut items = Vec::new();
+        let mut item = Self::parse_item(parser, tokenizer, token)?;
+
+        // The list ends at a line that is not an item, an item indented
+        // less than this list, or an item of the other kind of list
+        while let Some ((n, marker)) = tokenizer.peek_next_line(TokenClass::ListItem) {
+            let marker_column = marker.get_span().column;
+            if marker_column < column || (marker_column == column && Self::is_ordered(&marker) != ordered) {
+                break;
+            }
+
+            for _ in 0..=n {
+                tokenizer.next();
+            }
+
+            if marker_column > column {
+                item.push(self.parse(parser, tokenizer, &marker)?);
+            } else {
+                items.push(item);
+                item = Self::parse_item(parser, tokenizer, &marker)?;
+            }
+        }
+        items.push(item);
+
+        if ordered {
+            let start = token.get_value()
+                .trim_end_matches('.')
+                .parse()
+                .unwrap_or(1);
+
+            Ok (Expression::OrderedList {
+                start,
+                items,
+            })
+        } else {
+            Ok (Expression::UnorderedList (items))
+        }
+    }
+}
\ No newline at end of file
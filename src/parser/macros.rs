@@ -0,0 +1,82 @@
+//! User-defined macros for the Blog Builder parser module.
+//!
+//! A macro is defined with `\def{name}{parameters}{body}`, and is then
+//! invoked as `\name{argument}...` with one argument for each parameter.
+//! Its body refers to its arguments as `#1` to `#9`:
+//!
+//! ```text
+//! \def{callout}{2}{\block{#1}{#2}}
+//! ~ \callout{Simplicity is prerequisite for reliability.}{Edsger Dijkstra}
+//! ```
+
+/// Greatest number of parameters that a macro may have.
+pub const MAX_PARAMETERS: usize = 9;
+
+/// Greatest depth to which macros may be expanded within one another,
+/// which stops recursive macros from expanding forever.
+pub const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Macro defined by the author of a page or site.
+pub struct Macro {
+    /// Number of arguments taken by the macro.
+    parameters: usize,
+
+    /// Source text that the macro expands into.
+    body: String,
+}
+
+impl Macro {
+    /// Constructs a new macro.
+    pub fn new(parameters: usize, body: String) -> Self {
+        Self {
+            parameters,
+            body,
+        }
+    }
+
+    /// Gets the number of arguments taken by the macro.
+    pub fn get_parameters(&self) -> usize {
+        self.parameters
+    }
+
+    /// Expands the macro, replacing each parameter in its body with the
+    /// source text of the matching argument.
+    pub fn expand(&self, arguments: &[String]) -> String {
+        let mut expansion = String::new();
+        let mut chars = self.body.chars().peekable();
+
+        while let Some (c) = chars.next() {
+            match c {
+                // Escaped characters, such as `\#`, are kept as they are
+                '\\' => {
+                    expansion.push(c);
+                    if let Some (e) = chars.next() {
+                        expansion.push(e);
+                    }
+                },
+                '#' => {
+                    let argument = chars.peek()
+                        .and_then(|d| d.to_digit(10))
+                        .and_then(|d| arguments.get((d as usize).checked_sub(1)?));
+
+                    match argument {
+                        Some (a) => {
+                            chars.next();
+                            expansion.push_str(a);
+                        },
+                        None => expansion.push(c),
+                    }
+                },
+                c => expansion.push(c),
+            }
+        }
+
+        expansion
+    }
+}
+
+/// Checks whether a name may be given to a macro.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
@@ -1,6 +1,10 @@
 //! Parser module for the Blog Builder.
 
 mod expression;
+mod extension;
+mod front_matter;
+mod macros;
+#[allow(clippy::module_inception)]
 mod parser;
 mod prefix_parselet;
 mod tokenizer;
@@ -8,22 +12,43 @@ mod tokenizer;
 mod alphanumeric_parselet;
 mod control_parselet;
 mod hash_parselet;
+mod list_parselet;
 mod newline_parselet;
 mod paragraph_parselet;
+mod table_parselet;
 
-pub use expression::Expression;
+pub use expression::{
+    Alignment,
+    Expression,
+    Row,
+};
 
-pub use parser::Parser;
+pub use extension::{
+    ControlHandler,
+    Extension,
+};
+
+pub use front_matter::PageMeta;
+
+pub use macros::Macro;
+
+pub use parser::{
+    Parser,
+    ParserBuilder,
+};
 
 pub use prefix_parselet::PrefixParselet;
 
 pub use alphanumeric_parselet::AlphanumericParselet;
 pub use control_parselet::ControlParselet;
 pub use hash_parselet::HashParselet;
+pub use list_parselet::ListParselet;
 pub use newline_parselet::NewlineParselet;
 pub use paragraph_parselet::ParagraphParselet;
+pub use table_parselet::TableParselet;
 
 pub use tokenizer::{
+    Span,
     Token,
     TokenClass,
     Tokenizer,
@@ -1,5 +1,7 @@
 //! Newline parselet.
 
+use crate::Error;
+
 use super::{
     Expression,
     Parser,
@@ -13,17 +15,18 @@ use super::{
 pub struct NewlineParselet;
 
 impl PrefixParselet for NewlineParselet {
-    fn parse(&self, _: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Expression {
+    fn parse(&self, _: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Result<Expression, Error> {
         while let Some(t) = tokenizer.peek() {
-            if t.get_class() == TokenClass::Newline {
-                tokenizer.next();
-            } else if t.get_class() == TokenClass::Alphanumeric && t.get_value().len() == 0 {
+            let is_newline = t.get_class() == TokenClass::Newline;
+            let is_blank = t.get_class() == TokenClass::Alphanumeric && t.get_value().trim().is_empty();
+
+            if is_newline || is_blank {
                 tokenizer.next();
             } else {
                 break;
             }
         }
 
-        Expression::Newline
+        Ok (Expression::Newline)
     }
 }
\ No newline at end of file
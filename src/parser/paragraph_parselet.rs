@@ -1,5 +1,7 @@
 //! Paragraph parselet.
 
+use crate::Error;
+
 use super::{
     Expression,
     Parser,
@@ -12,9 +14,10 @@ use super::{
 pub struct ParagraphParselet;
 
 impl PrefixParselet for ParagraphParselet {
-    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Expression {
-        let inside = parser.parse_tokens(tokenizer, token.get_precedence());
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Result<Expression, Error> {
+        let mut inside = parser.parse_tokens(tokenizer, token.get_precedence())?;
+        Expression::trim(&mut inside);
 
-        Expression::Paragraph (inside)
+        Ok (Expression::Paragraph (inside))
     }
 }
\ No newline at end of file
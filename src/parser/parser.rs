@@ -1,12 +1,24 @@
 //! Parser for the Blog Builder.
 
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::Error;
 
 use super::{
+    macros::MAX_DEPTH,
+    ControlHandler,
     Expression,
+    Macro,
+    PageMeta,
     PrefixParselet,
+    Span,
+    Token,
     TokenClass,
     Tokenizer,
 };
@@ -15,61 +27,369 @@ use super::{
     AlphanumericParselet,
     ControlParselet,
     HashParselet,
+    ListParselet,
     NewlineParselet,
     ParagraphParselet,
+    TableParselet,
 };
 
 pub struct Parser {
-    prefix_parselets: HashMap<TokenClass, Box<dyn PrefixParselet>>,
+    prefix_parselets: HashMap<TokenClass, Arc<dyn PrefixParselet>>,
+    controls: HashMap<String, Arc<dyn ControlHandler>>,
+    errors: RefCell<Vec<Error>>,
+    dir: RefCell<PathBuf>,
+    including: RefCell<Vec<PathBuf>>,
+    macros: RefCell<HashMap<String, Macro>>,
+    depth: Cell<usize>,
+    too_deep: Cell<bool>,
 }
 
 impl Parser {
     pub fn new() -> Self {
-        let mut prefix_parselets: HashMap<TokenClass, Box<dyn PrefixParselet>> = HashMap::new();
+        Self::builder().build()
+    }
 
-        prefix_parselets.insert(TokenClass::Alphanumeric, Box::new(AlphanumericParselet {}));
-        prefix_parselets.insert(TokenClass::BeginParagraph, Box::new(ParagraphParselet {}));
-        prefix_parselets.insert(TokenClass::Control, Box::new(ControlParselet {}));
-        prefix_parselets.insert(TokenClass::Hash, Box::new(HashParselet {}));
-        prefix_parselets.insert(TokenClass::Newline, Box::new(NewlineParselet {}));
+    /// Starts building a parser with added control sequences or parselets.
+    pub fn builder() -> ParserBuilder {
+        ParserBuilder::new()
+    }
 
-        Self {
-            prefix_parselets,
+    /// Sets the directory containing the page being parsed,
+    /// against which the files that it reads are resolved.
+    pub fn in_dir(self, dir: &Path) -> Self {
+        self.dir.replace(dir.to_path_buf());
+        self
+    }
+
+    /// Sets the page being parsed, so that the files that it reads are
+    /// resolved against its directory and it cannot include itself.
+    pub fn in_page(self, page: &Path) -> Self {
+        self.including.replace(vec![page.to_path_buf()]);
+        self.in_dir(page.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Resolves the path of a file read by the page being parsed.
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.dir.borrow().join(path)
+    }
+
+    /// Parses another source file included by the given control sequence,
+    /// resolving the files that it reads against its own directory.
+    pub fn include(&self, command: &Token, file: &str) -> Result<Expression, Error> {
+        let path = self.resolve(file);
+        let contents = match fs::read_to_string(&path) {
+            Ok (c) => c,
+            Err (e) => return Err (Error::CannotOpenFile {
+                path,
+                source: e,
+            }),
+        };
+
+        // A file that is already being parsed would include itself forever
+        let canonical = path.canonicalize().ok();
+        let cycle = self.including.borrow()
+            .iter()
+            .position(|p| p.canonicalize().ok() == canonical)
+            .map(|i| self.including.borrow()[i..].to_vec());
+        if let Some (mut cycle) = cycle {
+            cycle.push(path);
+            return Err (Error::IncludeCycle {
+                span: command.get_span(),
+                cycle,
+            });
         }
+
+        self.including.borrow_mut().push(path.to_owned());
+        let dir = self.dir.replace(path.parent().unwrap_or(Path::new("")).to_path_buf());
+
+        let mut tokenizer = Tokenizer::new(contents.to_owned());
+        let expressions = self.parse_in_file(&mut tokenizer, &path, &contents);
+
+        self.dir.replace(dir);
+        self.including.borrow_mut().pop();
+
+        let mut expressions = expressions?;
+        Expression::trim(&mut expressions);
+
+        Ok (Expression::Include {
+            file: path.display().to_string(),
+            expressions,
+        })
     }
 
-    pub fn parse_tokens(&self, tokenizer: &mut Tokenizer, precedence: usize) -> Vec<Expression> {
+    /// Gets the handler for a control sequence added to the parser, if there is one.
+    pub fn get_control(&self, name: &str) -> Option<&dyn ControlHandler> {
+        self.controls.get(name).map(|c| c.as_ref())
+    }
+
+    /// Parses an argument `{...}` of a control sequence as expressions.
+    pub fn parse_argument(&self, tokenizer: &mut Tokenizer) -> Result<Vec<Expression>, Error> {
+        tokenizer.discard(TokenClass::OpenCurly)?;
+        let inside = self.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+        tokenizer.discard(TokenClass::CloseCurly)?;
+
+        Ok (inside)
+    }
+
+    /// Reads an argument `{...}` of a control sequence as plain text.
+    pub fn text_argument(&self, tokenizer: &mut Tokenizer) -> Result<String, Error> {
+        tokenizer.discard(TokenClass::OpenCurly)?;
+        let text = tokenizer.discard_text()?;
+        tokenizer.discard(TokenClass::CloseCurly)?;
+
+        Ok (text)
+    }
+
+    /// Defines a macro, replacing any earlier macro of the same name.
+    pub fn define(&self, name: &str, definition: Macro) {
+        self.macros.borrow_mut().insert(name.to_string(), definition);
+    }
+
+    /// Gets the macro with the given name, if one is defined.
+    pub fn get_macro(&self, name: &str) -> Option<Macro> {
+        self.macros.borrow().get(name).cloned()
+    }
+
+    /// Defines the macros in a file, such as the site's macros file, for
+    /// every page parsed afterwards.  Anything else in the file is ignored.
+    pub fn load_macros(&self, path: &Path, contents: &str) -> Result<(), Error> {
+        let (_, errors) = self.parse_recover(contents);
+
+        if errors.is_empty() {
+            Ok (())
+        } else {
+            Err (Error::from_list(errors).in_file(path, contents))
+        }
+    }
+
+    /// Parses the expansion of a macro, whose name is invoked at the given span.
+    /// 
+    /// Errors in the expansion are reported at the invocation, since the
+    /// expansion does not appear in the page.  Once macros have been
+    /// expanded too deeply within one another, no more are expanded,
+    /// so that a recursive macro is reported only once.
+    pub fn parse_expansion(&self, name: &str, span: Span, expansion: String) -> Result<Vec<Expression>, Error> {
+        if self.too_deep.get() {
+            return Ok (Vec::new());
+        }
+
+        if self.depth.get() >= MAX_DEPTH {
+            self.too_deep.set(true);
+            return Err (Error::InvalidMacro {
+                name: name.to_string(),
+                span,
+                message: format!("expands more than {} levels deep (does it invoke itself?)", MAX_DEPTH),
+            });
+        }
+
+        self.depth.set(self.depth.get() + 1);
+        let before = self.errors.borrow().len();
+        let mut tokenizer = Tokenizer::new(expansion);
+        let expressions = self.parse_tokens(&mut tokenizer, 0);
+        self.depth.set(self.depth.get() - 1);
+
+        let mut errors = self.errors.borrow_mut();
+        let found = errors.split_off(before);
+        errors.extend(found.into_iter().map(|e| e.in_macro(name, span)));
+
+        let mut expressions = expressions.map_err(|e| e.in_macro(name, span))?;
+        Expression::trim(&mut expressions);
+
+        Ok (expressions)
+    }
+
+    /// Parses tokens read from another file, such as a CSV file, so that
+    /// the errors found in them are reported against that file.
+    pub fn parse_in_file(&self, tokenizer: &mut Tokenizer, path: &Path, contents: &str) -> Result<Vec<Expression>, Error> {
+        let before = self.errors.borrow().len();
+        let expressions = self.parse_tokens(tokenizer, 0);
+
+        let mut errors = self.errors.borrow_mut();
+        let found = errors.split_off(before);
+        errors.extend(found.into_iter().map(|e| e.in_file(path, contents)));
+
+        expressions.map_err(|e| e.in_file(path, contents))
+    }
+
+    /// Parses tokens with precedence greater than the given precedence.
+    /// 
+    /// Errors are recorded and the parser resynchronises at the next
+    /// newline or closing brace, so that the rest of the input can
+    /// still be checked.
+    pub fn parse_tokens(&self, tokenizer: &mut Tokenizer, precedence: usize) -> Result<Vec<Expression>, Error> {
         let mut expressions = Vec::new();
 
         while let Some(token) = tokenizer.peek() {
             if token.get_precedence() > precedence {
                 tokenizer.next();
 
-                let prefix_parselet = match self.prefix_parselets.get(&token.get_class()) {
-                    Some (p) => p,
-                    None => Error::UnrecognizedToken.throw_msg(
-                        &format!(
-                            "`{}` (of type {})",
-                            &token.get_value(),
-                            token.get_class(),
-                        ),
-                    ),
+                let result = match self.prefix_parselets.get(&token.get_class()) {
+                    Some (p) => p.parse(self, tokenizer, &token),
+                    None => Err (Error::UnrecognizedToken (token)),
                 };
 
-                let expression = prefix_parselet.parse(self, tokenizer, &token);
-
-                expressions.push(expression);
+                match result {
+                    // Groups, such as expanded macros, are spliced in
+                    Ok (Expression::Group (v)) => expressions.extend(v),
+                    Ok (expression) => expressions.push(expression),
+                    Err (e) => {
+                        self.errors.borrow_mut().push(e);
+                        Self::synchronize(tokenizer);
+                    },
+                }
             } else {
                 break;
             }
         }
 
-        expressions
+        Ok (expressions)
+    }
+
+    /// Skips tokens up to the next newline or unmatched closing brace.
+    fn synchronize(tokenizer: &mut Tokenizer) {
+        let mut depth = 0;
+
+        while let Some(t) = tokenizer.peek() {
+            match t.get_class() {
+                TokenClass::Newline => break,
+                TokenClass::CloseCurly if depth == 0 => break,
+                TokenClass::CloseCurly => depth -= 1,
+                TokenClass::OpenCurly => depth += 1,
+                _ => (),
+            };
+            tokenizer.next();
+        }
+    }
+
+    /// Parses the input, returning every expression that could be parsed
+    /// along with every error encountered.
+    pub fn parse_recover(&self, input: &str) -> (Vec<Expression>, Vec<Error>) {
+        self.parse_tokenizer(Tokenizer::new(input.to_string()))
+    }
+
+    /// Parses a page, starting with its front matter, returning the page's
+    /// metadata and every expression that could be parsed along with
+    /// every error encountered.
+    /// 
+    /// If the front matter is invalid, the rest of the page is not parsed.
+    pub fn parse_page(&self, input: &str) -> (PageMeta, Vec<Expression>, Vec<Error>) {
+        let (meta, start) = match PageMeta::split(input) {
+            Ok (m) => m,
+            Err (e) => return (PageMeta::default(), Vec::new(), vec![e]),
+        };
+
+        let body = input[start.offset..].to_string();
+        let (expressions, errors) = self.parse_tokenizer(Tokenizer::new_at(body, start));
+
+        (meta, expressions, errors)
+    }
+
+    /// Parses every token from a tokenizer.
+    fn parse_tokenizer(&self, mut tokenizer: Tokenizer) -> (Vec<Expression>, Vec<Error>) {
+        self.errors.borrow_mut().clear();
+        self.too_deep.set(false);
+
+        let expressions = match self.parse_tokens(&mut tokenizer, 0) {
+            Ok (e) => e,
+            Err (e) => {
+                self.errors.borrow_mut().push(e);
+                Vec::new()
+            },
+        };
+
+        (expressions, self.errors.take())
+    }
+
+    pub fn parse(&self, input: &str) -> Result<Vec<Expression>, Error> {
+        let (expressions, errors) = self.parse_recover(input);
+
+        if errors.is_empty() {
+            Ok (expressions)
+        } else {
+            Err (Error::from_list(errors))
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+/// Builder for a parser with added control sequences or parselets.
+/// 
+/// A builder may build any number of parsers, such as one for each
+/// page of a site.
+pub struct ParserBuilder {
+    prefix_parselets: HashMap<TokenClass, Arc<dyn PrefixParselet>>,
+    controls: HashMap<String, Arc<dyn ControlHandler>>,
+}
+
+impl ParserBuilder {
+    /// Starts building a parser with the built-in parselets.
+    pub fn new() -> Self {
+        let mut prefix_parselets: HashMap<TokenClass, Arc<dyn PrefixParselet>> = HashMap::new();
+
+        prefix_parselets.insert(TokenClass::Alphanumeric, Arc::new(AlphanumericParselet {}));
+        prefix_parselets.insert(TokenClass::BeginParagraph, Arc::new(ParagraphParselet {}));
+        prefix_parselets.insert(TokenClass::Control, Arc::new(ControlParselet {}));
+        prefix_parselets.insert(TokenClass::Hash, Arc::new(HashParselet {}));
+        prefix_parselets.insert(TokenClass::ListItem, Arc::new(ListParselet {}));
+        prefix_parselets.insert(TokenClass::Newline, Arc::new(NewlineParselet {}));
+        prefix_parselets.insert(TokenClass::Pipe, Arc::new(TableParselet {}));
+
+        Self {
+            prefix_parselets,
+            controls: HashMap::new(),
+        }
+    }
+
+    /// Adds a control sequence `\name`, parsed by the given handler.
+    ///
+    /// Built-in control sequences take precedence over added ones,
+    /// which in turn take precedence over macros.
+    pub fn control(mut self, name: &str, handler: impl ControlHandler + 'static) -> Self {
+        self.controls.insert(name.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Parses tokens of the given class with the given parselet,
+    /// replacing the built-in parselet for that class.
+    pub fn prefix(mut self, class: TokenClass, parselet: impl PrefixParselet + 'static) -> Self {
+        self.prefix_parselets.insert(class, Arc::new(parselet));
+        self
+    }
+
+    /// Lists the token class or name and the version of every parselet
+    /// and added control sequence, in order.
+    pub fn get_versions(&self) -> Vec<String> {
+        let mut versions = self.prefix_parselets.iter()
+            .map(|(c, p)| format!("prefix {:?} {}", c, p.version()))
+            .chain(self.controls.iter().map(|(n, h)| format!("control {} {}", n, h.version())))
+            .collect::<Vec<String>>();
+        versions.sort();
+        versions
+    }
+
+    pub fn build(&self) -> Parser {
+        Parser {
+            prefix_parselets: self.prefix_parselets.clone(),
+            controls: self.controls.clone(),
+            errors: RefCell::new(Vec::new()),
+            dir: RefCell::new(PathBuf::new()),
+            including: RefCell::new(Vec::new()),
+            macros: RefCell::new(HashMap::new()),
+            depth: Cell::new(0),
+            too_deep: Cell::new(false),
+        }
     }
+}
 
-    pub fn parse(&self, input: &str) -> Vec<Expression> {
-        let mut tokenizer = Tokenizer::new(input.to_string());
-        self.parse_tokens(&mut tokenizer, 0)
+impl Default for ParserBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -78,7 +398,222 @@ fn simple_parse() {
     let example = "# Hello, \\href{world}{www.google.com}!\nHave you ## ever tried using \\href{Google \\bold{Search}}{www.google.com}?";
     let parser = Parser::new();
     println!("{}", example);
-    let expressions = parser.parse(example);
+    let expressions = parser.parse(example).unwrap();
     println!("Parsed results");
     dbg!(expressions);
-}
\ No newline at end of file
+}
+
+#[test]
+fn unrecognized_control_sequence() {
+    let parser = Parser::new();
+    let result = parser.parse("~ This is \\nonsense{text}");
+    assert!(matches!(result, Err (Error::UnrecognizedControlSequence (t)) if t.get_value() == "nonsense"));
+}
+
+#[test]
+fn error_excerpt() {
+    let parser = Parser::new();
+    let source = "~ Hello\n~ See \\href{here}[oops]";
+    let error = parser.parse(source)
+        .unwrap_err()
+        .in_file(std::path::Path::new("page.txt"), source);
+    assert_eq!(
+        error.to_string(),
+        "[ERROR] page.txt:2:18: expected token of class: OpenCurly, found: Alphanumeric (`[oops]`)\n  |\n2 | ~ See \\href{here}[oops]\n  |                  ^^^^^^",
+    );
+}
+
+#[test]
+fn recover_from_errors() {
+    let parser = Parser::new();
+    let source = "~ \\wat{one}\n~ Fine \\bold{text}\n~ \\href{two}[three]\n~ End";
+    let (expressions, errors) = parser.parse_recover(source);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(expressions.len(), 4);
+}
+
+#[test]
+fn inline_whitespace() {
+    let parser = Parser::new();
+    let expressions = parser.parse("~   See \\href{this}{ /page.html }. A \\bold{word}, \\it{then} more.  \n").unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<p>See <a href=\"/page.html\">this</a>. A <strong>word</strong>, <em>then</em> more.</p>",
+    );
+}
+
+#[test]
+fn raw_html() {
+    let parser = Parser::new();
+    let expressions = parser.parse("~ A \\raw{<svg viewBox=\"0 0 1 1\"><style>rect { fill: red; }</style></svg>} & more").unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<p>A <svg viewBox=\"0 0 1 1\"><style>rect { fill: red; }</style></svg> &amp; more</p>",
+    );
+
+    // Raw HTML is kept exactly as written, backslashes included
+    let expressions = parser.parse("\\raw{<script>var r = /a\\.b/; 'x\\-y'</script>}").unwrap();
+    assert_eq!(Expression::render_all(&expressions).unwrap(), "<script>var r = /a\\.b/; 'x\\-y'</script>");
+
+    // HTML and code files are found relative to the page
+    let dir = crate::test_dir::TestDir::new("raw-html");
+    dir.write("posts/widget.html", "<form></form>");
+    dir.write("posts/main.rs", "fn main() {}");
+    let parser = Parser::new().in_dir(&dir.join("posts"));
+    let expressions = parser.parse("\\html{widget.html}").unwrap();
+    assert_eq!(expressions[0].get_dependencies(), vec![dir.join("posts/widget.html")]);
+    assert_eq!(Expression::render_all(&expressions).unwrap(), "<form></form>");
+
+    let expressions = parser.parse("\\code{rust}{main.rs}").unwrap();
+    assert_eq!(expressions[0].get_dependencies(), vec![dir.join("posts/main.rs")]);
+    assert!(Expression::render_all(&expressions).unwrap().contains("fn main() {}"));
+}
+
+#[test]
+fn lists() {
+    let parser = Parser::new();
+    let source = "~ Steps:\n- First \\bold{step}\n  3. Nested\n  4. Again\n- Second\n1. Ordered\n\n~ Not a list: - dash and 2. number\n\\- escaped";
+    let expressions = parser.parse(source).unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<p>Steps:</p>\n<ul>\n<li>First <strong>step</strong><ol start=\"3\">\n<li>Nested</li>\n<li>Again</li>\n</ol></li>\n<li>Second</li>\n</ul>\n<ol>\n<li>Ordered</li>\n</ol><p>Not a list: - dash and 2. number\n- escaped</p>",
+    );
+}
+
+#[test]
+fn tables() {
+    let parser = Parser::new();
+    let source = "| Name | Price |\n|:-----|------:|\n| \\bold{Tea} | 3 \\| 4 |\n\\caption{Prices}";
+    let expressions = parser.parse(source).unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<table>\n<caption>Prices</caption>\n<thead>\n<tr><th style=\"text-align: left;\">Name</th><th style=\"text-align: right;\">Price</th></tr>\n</thead>\n<tbody>\n<tr><td style=\"text-align: left;\"><strong>Tea</strong></td><td style=\"text-align: right;\">3 | 4</td></tr>\n</tbody>\n</table>",
+    );
+
+    let dir = crate::test_dir::TestDir::new("tables");
+    dir.write("data.csv", "Name,Notes\r\nTea,\"Hot, \\it{strong}\"\r\n\"Say \"\"hi\"\"\",\n");
+    let parser = Parser::new().in_dir(dir.path());
+    let expressions = parser.parse("~ a | b\n\\table{data.csv}{-c}").unwrap();
+    assert_eq!(expressions[0].get_dependencies(), vec![dir.join("data.csv")]);
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<p>a | b</p>\n<table>\n<thead>\n<tr><th>Name</th><th style=\"text-align: center;\">Notes</th></tr>\n</thead>\n<tbody>\n<tr><td>Tea</td><td style=\"text-align: center;\">Hot, <em>strong</em></td></tr>\n<tr><td>Say \"hi\"</td><td style=\"text-align: center;\"></td></tr>\n</tbody>\n</table>",
+    );
+
+    // Cells of CSV files are inline text, even where they look like
+    // headings, lists or paragraphs
+    dir.write("picks.csv", "Pick,Time\n#1 pick,~5 min\n- 3,1. x\n");
+    let expressions = parser.parse("\\table{picks.csv}").unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<table>\n<thead>\n<tr><th>Pick</th><th>Time</th></tr>\n</thead>\n<tbody>\n<tr><td>#1 pick</td><td>~5 min</td></tr>\n<tr><td>- 3</td><td>1. x</td></tr>\n</tbody>\n</table>",
+    );
+}
+
+#[test]
+fn macros() {
+    let parser = Parser::new();
+    parser.load_macros(std::path::Path::new("macros.txt"), "\\def{sig}{0}{\\it{Jo}}").unwrap();
+
+    let source = "\\def{callout}{2}{\\bold{#1}: #2 \\#1}\n~ \\callout{Note}{see \\href{here}{/a.html}}, \\sig";
+    let expressions = parser.parse(source).unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "\n<p><strong>Note</strong>: see <a href=\"/a.html\">here</a> #1, <em>Jo</em></p>",
+    );
+
+    let (_, errors) = parser.parse_recover("\\def{loop}{0}{\\loop\\loop}\n~ \\loop");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().contains("invalid macro `\\loop`: expands more than 16 levels deep"));
+
+    let error = parser.parse("\\def{many}{10}{}").unwrap_err();
+    assert!(error.to_string().contains("number of parameters must be from 0 to 9"));
+
+    // Macros without arguments end at whitespace or an empty `{}`
+    let expressions = parser.parse("\\def{site}{0}{My Blog}\n~ Welcome to \\site today, \\site{}! \\site").unwrap();
+    assert_eq!(Expression::render_all(&expressions).unwrap(), "\n<p>Welcome to My Blog today, My Blog! My Blog</p>");
+
+    let error = parser.parse("\\def{two}{2}{#1#2}\n~ \\two x{a}{b}").unwrap_err();
+    assert!(error.to_string().contains("invalid macro `\\two`: expected 2 arguments directly after its name"));
+
+    // Errors in the body of a macro are reported where it is invoked
+    let source = "\\def{bad}{0}{\\nope}\n\\def{outer}{0}{x \\bad}\n~ call \\outer here";
+    let error = parser.parse(source)
+        .unwrap_err()
+        .in_file(std::path::Path::new("page.txt"), source);
+    assert_eq!(
+        error.to_string(),
+        "[ERROR] page.txt:3:9: in macro `\\outer`: in macro `\\bad`: unrecognized control sequence: `\\nope`\n  |\n3 | ~ call \\outer here\n  |         ^^^^^",
+    );
+}
+
+#[test]
+fn extensions() {
+    use super::Extension;
+    use crate::{Renderer, Renderers};
+
+    struct YouTube;
+
+    impl ControlHandler for YouTube {
+        fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Result<Expression, Error> {
+            let id = parser.text_argument(tokenizer)?;
+            let caption = parser.parse_argument(tokenizer)?;
+
+            Ok (Expression::Extension (Extension::new("youtube", vec![id], vec![caption]).block()))
+        }
+    }
+
+    impl Renderer for YouTube {
+        fn render(&self, extension: &Extension, children: &[String]) -> Result<String, Error> {
+            Ok (format!("<figure data-video=\"{}\">{}</figure>", extension.get_arguments()[0], children[0]))
+        }
+    }
+
+    let parser = Parser::builder()
+        .control("youtube", YouTube)
+        .build();
+    let expressions = parser.parse("~ Watch \\youtube{abc}{A \\bold{talk}} now").unwrap();
+
+    let mut renderers = Renderers::new();
+    renderers.insert("youtube", YouTube);
+    assert_eq!(
+        Expression::render_all_with(&expressions, &renderers).unwrap(),
+        "<p>Watch</p>\n<figure data-video=\"abc\">A <strong>talk</strong></figure>\n<p>now</p>",
+    );
+
+    let error = Expression::render_all(&expressions).unwrap_err();
+    assert_eq!(error.to_string(), "[ERROR] cannot render `\\youtube`: no renderer is registered for it");
+
+    // Control sequences are only added to parsers built with them
+    assert!(Parser::new().parse("\\youtube{abc}{}").is_err());
+}
+
+#[test]
+fn includes() {
+    let dir = crate::test_dir::TestDir::new("includes");
+    dir.write("partials/bio.txt", "~ By \\include{name.txt}.\n");
+    dir.write("partials/name.txt", "\\bold{Jo}\n");
+    dir.write("partials/loop.txt", "~ Again \\include{../page.txt}");
+
+    let parser = Parser::new().in_page(&dir.join("page.txt"));
+    let expressions = parser.parse("~ Hello\n\\include{partials/bio.txt}").unwrap();
+    assert_eq!(
+        Expression::render_all(&expressions).unwrap(),
+        "<p>Hello</p>\n<p>By <strong>Jo</strong>.</p>",
+    );
+    assert_eq!(
+        expressions.iter().flat_map(|e| e.get_dependencies()).collect::<Vec<PathBuf>>(),
+        vec![dir.join("partials/bio.txt"), dir.join("partials/name.txt")],
+    );
+
+    dir.write("page.txt", "\\include{partials/loop.txt}");
+    let (_, errors) = Parser::new().in_page(&dir.join("page.txt")).parse_recover("\\include{partials/loop.txt}");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().contains(&format!(
+        "loop.txt:1:10: include cycle: {} includes {} includes {}",
+        dir.join("page.txt").display(),
+        dir.join("partials/loop.txt").display(),
+        dir.join("partials/../page.txt").display(),
+    )));
+}
+
@@ -1,5 +1,7 @@
 //! Prefix parselets for the Blog Builder parser.
 
+use crate::Error;
+
 use super::{
     Expression,
     Parser,
@@ -8,6 +10,13 @@ use super::{
 };
 
 /// Prefix parselet abstraction.
-pub trait PrefixParselet {
-    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Expression;
+pub trait PrefixParselet: Send + Sync {
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, token: &Token) -> Result<Expression, Error>;
+
+    /// Identifies this parselet and the version of its output, so that
+    /// builds compile pages again when it changes.  Defaults to the name
+    /// of its type.
+    fn version(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,264 @@
+//! Table parselet.
+
+use std::{
+    fs,
+    path::Path,
+};
+
+use crate::Error;
+
+use super::{
+    Alignment,
+    Expression,
+    Parser,
+    PrefixParselet,
+    Row,
+    Span,
+    Token,
+    TokenClass,
+    Tokenizer,
+};
+
+/// Parselet for tables written as rows of cells separated by `|`.
+///
+/// Rows above a separator row, such as `|:---|:---:|---:|`, are column
+/// headings, and the colons in the separator align each column.  A table
+/// may be followed by a `\caption` on the next line.
+pub struct TableParselet;
+
+impl TableParselet {
+    /// Parses the cells of a table row, following its opening `'|'`.
+    fn parse_row(parser: &Parser, tokenizer: &mut Tokenizer) -> Result<Row, Error> {
+        let mut cells = Vec::new();
+
+        loop {
+            let mut cell = parser.parse_tokens(tokenizer, TokenClass::Pipe.get_precedence())?;
+            Expression::trim(&mut cell);
+
+            match tokenizer.peek() {
+                Some (t) if t.get_class() == TokenClass::Pipe => {
+                    tokenizer.next();
+                    cells.push(cell);
+                },
+                // The closing `'|'` of a row is optional
+                _ => {
+                    if !cell.is_empty() {
+                        cells.push(cell);
+                    }
+                    break;
+                },
+            }
+        }
+
+        Ok (cells)
+    }
+
+    /// Gets the alignment of each column from a separator row, or `None`
+    /// if the row is not a separator.
+    fn parse_separator(row: &Row) -> Option<Vec<Alignment>> {
+        if row.is_empty() {
+            return None;
+        }
+
+        row.iter()
+            .map(|cell| match cell.as_slice() {
+                [Expression::Alphanumeric (s)] => {
+                    let s = s.trim();
+                    let dashes = s.strip_prefix(':').unwrap_or(s);
+                    let dashes = dashes.strip_suffix(':').unwrap_or(dashes);
+                    if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                        return None;
+                    }
+
+                    match (s.starts_with(':'), s.ends_with(':')) {
+                        (true, true) => Some (Alignment::Center),
+                        (true, false) => Some (Alignment::Left),
+                        (false, true) => Some (Alignment::Right),
+                        (false, false) => Some (Alignment::Default),
+                    }
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Parses the `\caption{...}` on the line after a table, if there is one.
+    pub fn parse_caption(parser: &Parser, tokenizer: &mut Tokenizer) -> Result<Vec<Expression>, Error> {
+        let n = match tokenizer.peek_next_line(TokenClass::Control) {
+            Some ((n, _)) => n,
+            None => return Ok (Vec::new()),
+        };
+
+        match tokenizer.peek_nth(n + 1) {
+            Some (t) if t.get_class() == TokenClass::Alphanumeric && t.get_value().trim() == "caption" => (),
+            _ => return Ok (Vec::new()),
+        }
+
+        for _ in 0..n + 2 {
+            tokenizer.next();
+        }
+
+        tokenizer.discard(TokenClass::OpenCurly)?;
+        let mut caption = parser.parse_tokens(tokenizer, TokenClass::OpenCurly.get_precedence())?;
+        tokenizer.discard(TokenClass::CloseCurly)?;
+        Expression::trim(&mut caption);
+
+        Ok (caption)
+    }
+
+    /// Parses the alignment of each column from letters such as `lcr`,
+    /// where `l`, `c` and `r` align a column left, center and right,
+    /// and `-` leaves it to the stylesheet.
+    pub fn parse_alignments(letters: &str) -> Result<Vec<Alignment>, Error> {
+        letters.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                'l' => Ok (Alignment::Left),
+                'c' => Ok (Alignment::Center),
+                'r' => Ok (Alignment::Right),
+                '-' => Ok (Alignment::Default),
+                c => Err (Error::InvalidTable (format!("unknown alignment `{}` (expected l, c, r or -)", c))),
+            })
+            .collect()
+    }
+
+    /// Reads a table from a CSV file, whose first row holds the column headings.
+    ///
+    /// Cells may contain the same formatting as the rest of the page.
+    pub fn read_csv(parser: &Parser, file: &str, alignments: Vec<Alignment>, caption: Vec<Expression>) -> Result<Expression, Error> {
+        let path = parser.resolve(file);
+        let contents = match fs::read_to_string(&path) {
+            Ok (c) => c,
+            Err (e) => return Err (Error::CannotOpenFile {
+                path,
+                source: e,
+            }),
+        };
+
+        let records = match split_csv(&contents) {
+            Ok (r) => r,
+            Err (e) => return Err (e.in_file(&path, &contents)),
+        };
+
+        let mut rows = Vec::new();
+        for record in records {
+            let mut row = Vec::new();
+            for (offset, text) in record {
+                row.push(Self::parse_cell(parser, &path, &contents, offset, text)?);
+            }
+            rows.push(row);
+        }
+
+        let body = if rows.is_empty() {
+            Vec::new()
+        } else {
+            rows.split_off(1)
+        };
+
+        Ok (Expression::Table {
+            alignments,
+            header: rows,
+            body,
+            caption,
+            file: Some (path.display().to_string()),
+        })
+    }
+
+    /// Parses a cell of a CSV file, which begins at the given offset.
+    fn parse_cell(parser: &Parser, path: &Path, contents: &str, offset: usize, text: String) -> Result<Vec<Expression>, Error> {
+        let mut tokenizer = Tokenizer::new_inline(text, Span::locate(contents, offset, 0));
+        let mut cell = parser.parse_in_file(&mut tokenizer, path, contents)?;
+        Expression::trim(&mut cell);
+
+        Ok (cell)
+    }
+}
+
+impl PrefixParselet for TableParselet {
+    fn parse(&self, parser: &Parser, tokenizer: &mut Tokenizer, _: &Token) -> Result<Expression, Error> {
+        let mut alignments = Vec::new();
+        let mut header = Vec::new();
+        let mut body = vec![Self::parse_row(parser, tokenizer)?];
+
+        // The table continues for as long as lines begin with `'|'`
+        while let Some ((n, _)) = tokenizer.peek_next_line(TokenClass::Pipe) {
+            for _ in 0..=n {
+                tokenizer.next();
+            }
+
+            let row = Self::parse_row(parser, tokenizer)?;
+            match Self::parse_separator(&row) {
+                Some (a) if header.is_empty() => {
+                    alignments = a;
+                    header.append(&mut body);
+                },
+                _ => body.push(row),
+            }
+        }
+
+        let caption = Self::parse_caption(parser, tokenizer)?;
+
+        Ok (Expression::Table {
+            alignments,
+            header,
+            body,
+            caption,
+            file: None,
+        })
+    }
+}
+
+/// Splits CSV text into records of fields, each with the offset at which
+/// its text begins.
+///
+/// Fields are separated by commas and may be quoted with `"`, in which
+/// case they may contain commas, newlines and doubled quotes.
+fn split_csv(contents: &str) -> Result<Vec<Vec<(usize, String)>>, Error> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut chars = contents.char_indices().peekable();
+
+    while let Some ((i, c)) = chars.next() {
+        match c {
+            '"' if quoted => if chars.peek().is_some_and(|(_, c)| *c == '"') {
+                chars.next();
+                field.push('"');
+            } else {
+                quoted = false;
+            },
+            '"' if field.trim().is_empty() => {
+                quoted = true;
+                field.clear();
+                start = i + 1;
+            },
+            ',' if !quoted => {
+                record.push((start, std::mem::take(&mut field)));
+                start = i + 1;
+            },
+            '\n' if !quoted => {
+                record.push((start, std::mem::take(&mut field)));
+                records.push(std::mem::take(&mut record));
+                start = i + 1;
+            },
+            '\r' if !quoted => (),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err (Error::InvalidTable ("quoted field is not closed".to_string()));
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push((start, field));
+        records.push(record);
+    }
+
+    // Blank lines are not records
+    records.retain(|r| !(r.len() == 1 && r[0].1.trim().is_empty()));
+
+    Ok (records)
+}
@@ -8,15 +8,62 @@ use std::fmt::{
     Result,
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+/// Location of a token in the source text.
+pub struct Span {
+    /// Byte offset from the start of the source.
+    pub offset: usize,
+
+    /// Length in bytes.
+    pub length: usize,
+
+    /// Line number, starting at 1.
+    pub line: usize,
+
+    /// Column number in characters, starting at 1.
+    pub column: usize,
+}
+
+impl Span {
+    /// Locates a range of bytes in the source text.
+    pub fn locate(source: &str, offset: usize, length: usize) -> Self {
+        let offset = offset.min(source.len());
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Self {
+            offset,
+            length,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
 /// A character stream.
 pub struct Charstream {
     stream: Vec<char>,
     index: usize,
+    position: Span,
+    inline: bool,
+    line_start: usize,
+    indentation: bool,
 }
 
 impl Charstream {
     /// Construct a new character stream.
     pub fn new(input: String) -> Self {
+        Self::new_at(input, Span {
+            offset: 0,
+            length: 0,
+            line: 1,
+            column: 1,
+        })
+    }
+
+    /// Construct a new character stream over part of a source text,
+    /// which begins at the given position.
+    pub fn new_at(input: String, start: Span) -> Self {
         let stream = input.as_str()
             .chars()
             .collect::<Vec<char>>();
@@ -24,23 +71,81 @@ impl Charstream {
         Self {
             stream,
             index: 0,
+            position: start,
+            inline: false,
+            line_start: 0,
+            indentation: true,
         }
     }
 
+    /// Makes this character stream inline text, such as a table cell read
+    /// from a CSV file, in which headings, lists, paragraphs and table
+    /// rows are never recognized.
+    pub fn inline(mut self) -> Self {
+        self.inline = true;
+        self
+    }
+
+    /// Check whether this character stream is inline text.
+    pub fn is_inline(&self) -> bool {
+        self.inline
+    }
+
     /// Get the next character from this character stream.
     pub fn next(&mut self) -> Option<char> {
         let peek = self.peek();
         self.index += 1;
+
+        if let Some (c) = peek {
+            self.position.offset += c.len_utf8();
+            if c == '\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+                self.line_start = self.index;
+                self.indentation = true;
+            } else {
+                self.position.column += 1;
+                self.indentation &= c.is_whitespace();
+            }
+        }
+
         peek
     }
 
+    /// Get the current position of this character stream.
+    pub fn get_position(&self) -> Span {
+        self.position
+    }
+
+    /// Get the span from a given start position to the current position.
+    pub fn span_from(&self, start: Span) -> Span {
+        Span {
+            length: self.position.offset - start.offset,
+            ..start
+        }
+    }
+
     /// Peek at the next character from this character stream.
     pub fn peek(&self) -> Option<char> {
-        if self.index < self.stream.len() {
-            Some (self.stream[self.index])
-        } else {
-            None
-        }
+        self.peek_nth(0)
+    }
+
+    /// Peek at the character `n` places after the next character.
+    pub fn peek_nth(&self, n: usize) -> Option<char> {
+        self.stream.get(self.index + n).copied()
+    }
+
+    /// Check whether only whitespace precedes the next character on its line.
+    pub fn at_line_start(&self) -> bool {
+        self.indentation
+    }
+
+    /// Check whether the line containing the next character is a table
+    /// row, whose first character other than whitespace is `'|'`.
+    pub fn in_table_row(&self) -> bool {
+        self.stream[self.line_start..].iter()
+            .find(|c| !c.is_whitespace() || **c == '\n')
+            == Some (&'|')
     }
 }
 
@@ -49,14 +154,16 @@ impl Charstream {
 pub struct Token {
     class: TokenClass,
     value: String,
+    span: Span,
 }
 
 impl Token {
     /// Construct a new token.
-    pub fn new(class: TokenClass, value: &str) -> Self {
+    pub fn new(class: TokenClass, value: &str, span: Span) -> Self {
         Self {
             class,
             value: value.to_string(),
+            span,
         }
     }
 
@@ -74,6 +181,11 @@ impl Token {
     pub fn get_precedence(&self) -> usize {
         self.class.get_precedence()
     }
+
+    /// Get the location of the token in the source text.
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
@@ -97,6 +209,12 @@ pub enum TokenClass {
     /// Hash `'#'`.
     Hash,
 
+    /// List item marker, `'-'` or a number followed by `'.'`.
+    ListItem,
+
+    /// Pipe `'|'`, separating the cells of a table row.
+    Pipe,
+
     /// Newline character.
     Newline,
 }
@@ -109,17 +227,25 @@ impl TokenClass {
             '{' => Self::OpenCurly,
             '}' => Self::CloseCurly,
             '#' => Self::Hash,
+            '|' => Self::Pipe,
             '\n' => Self::Newline,
             _ => Self::Alphanumeric,
         }
     }
 
+    /// Check whether a character may be escaped with `'\'` to produce literal text.
+    pub fn is_escapable(c: char) -> bool {
+        matches!(c, '\\' | '{' | '}' | '#' | '~' | '-' | '.' | '|')
+    }
+
     pub fn get_precedence(&self) -> usize {
         match self {
             Self::Control => 5,
             Self::OpenCurly => 1,
             Self::CloseCurly => 1,
             Self::Hash => 4,
+            Self::ListItem => 4,
+            Self::Pipe => 4,
             Self::Newline => 3,
             Self::Alphanumeric => 6,
             Self::BeginParagraph => 2,
@@ -136,6 +262,8 @@ impl Display for TokenClass {
             OpenCurly => "OpenCurly",
             CloseCurly => "CloseCurly",
             Hash => "Hash",
+            ListItem => "ListItem",
+            Pipe => "Pipe",
             Newline => "Newline",
             Alphanumeric => "Alphanumeric",
             BeginParagraph => "BeginParagraph",
@@ -149,24 +277,48 @@ impl Display for TokenClass {
 pub struct Tokenizer {
     tokens: Vec<Token>,
     index: usize,
+    source: String,
+    start: usize,
+    end: Span,
 }
 
 impl Tokenizer {
     /// Constructs a new tokenizer.
     pub fn new(input: String) -> Self {
-        let mut charstream = Charstream::new(input);
+        Self::from_charstream(input.to_owned(), Charstream::new(input))
+    }
+
+    /// Constructs a new tokenizer over part of a source text,
+    /// which begins at the given position.
+    pub fn new_at(input: String, start: Span) -> Self {
+        Self::from_charstream(input.to_owned(), Charstream::new_at(input, start))
+    }
+
+    /// Constructs a new tokenizer over inline text, such as a table cell,
+    /// which begins at the given position.
+    pub fn new_inline(input: String, start: Span) -> Self {
+        Self::from_charstream(input.to_owned(), Charstream::new_at(input, start).inline())
+    }
+
+    /// Constructs a new tokenizer from a character stream over a source text.
+    fn from_charstream(source: String, mut charstream: Charstream) -> Self {
+        let start = charstream.get_position().offset;
         let mut tokens = Vec::new();
     
         while let Some(t) = Self::generate_next(&mut charstream) {
             tokens.push(t)
-        }    
+        }
 
         Self {
             tokens,
             index: 0,
+            source,
+            start,
+            end: charstream.get_position(),
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     /// Gets the next token from this token stream.
     pub fn next(&mut self) -> Option<Token> {
         let peek = self.peek();
@@ -176,95 +328,204 @@ impl Tokenizer {
 
     /// Peeks at the next token from this token stream.
     pub fn peek(&self) -> Option<Token> {
-        if self.index < self.tokens.len() {
-            Some (self.tokens[self.index].to_owned())
+        self.peek_nth(0)
+    }
+
+    /// Peeks at the token `n` places after the next token.
+    pub fn peek_nth(&self, n: usize) -> Option<Token> {
+        self.tokens.get(self.index + n).cloned()
+    }
+
+    /// Peeks at the first token of the next line, after any indentation,
+    /// if it is of the given class.  Returns the number of tokens before
+    /// it and the token itself.
+    pub fn peek_next_line(&self, class: TokenClass) -> Option<(usize, Token)> {
+        if self.peek()?.get_class() != TokenClass::Newline {
+            return None;
+        }
+
+        // Indentation is a separate token
+        let mut n = 1;
+        let next = self.peek_nth(n)?;
+        if next.get_class() == TokenClass::Alphanumeric && next.get_value().trim().is_empty() {
+            n += 1;
+        }
+
+        let first = self.peek_nth(n)?;
+        if first.get_class() == class {
+            Some ((n, first))
         } else {
             None
         }
     }
 
-    /// Discards a token of a given type, or throw an error.
-    pub fn discard(&mut self, class: TokenClass) -> String {
+    /// Takes a token of a given type, or returns an error.
+    pub fn expect(&mut self, class: TokenClass) -> std::result::Result<Token, Error> {
         if let Some(t) = self.next() {
             if t.get_class() == class {
-                t.get_value()
+                Ok (t)
             } else {
-                Error::ExpectedTokenOfClass.throw_msg(
-                    &format!(
-                        "{}, found: {} (`{}`)",
-                        class,
-                        t.get_class(),
-                        t.get_value(),
-                    ),
-                )
+                Err (Error::ExpectedTokenOfClass {
+                    expected: class,
+                    found: t,
+                })
             }
         } else {
-            Error::UnexpectedEof.throw()
+            Err (Error::UnexpectedEof (self.end))
+        }
+    }
+
+    /// Discards a token of a given type, or returns an error.
+    pub fn discard(&mut self, class: TokenClass) -> std::result::Result<String, Error> {
+        self.expect(class).map(|t| t.get_value())
+    }
+
+    /// Discards tokens up to the close curly brace matching an already
+    /// discarded open curly brace, returning their source text.
+    pub fn discard_raw(&mut self) -> std::result::Result<String, Error> {
+        let mut raw = String::new();
+        let mut depth = 0;
+
+        loop {
+            let t = match self.next() {
+                Some (t) => t,
+                None => return Err (Error::UnexpectedEof (self.end)),
+            };
+
+            match t.get_class() {
+                TokenClass::OpenCurly => depth += 1,
+                TokenClass::CloseCurly if depth == 0 => return Ok (raw),
+                TokenClass::CloseCurly => depth -= 1,
+                _ => (),
+            }
+
+            raw.push_str(&t.get_value());
+        }
+    }
+
+    /// Discards tokens up to the close curly brace matching an already
+    /// discarded open curly brace, returning their text exactly as it
+    /// is written in the source, escapes included.
+    pub fn discard_source(&mut self) -> std::result::Result<String, Error> {
+        let start = match self.peek() {
+            Some (t) => t.get_span().offset,
+            None => return Err (Error::UnexpectedEof (self.end)),
+        };
+
+        self.discard_raw()?;
+        let end = self.tokens[self.index - 1].get_span().offset;
+
+        Ok (self.source[start - self.start..end - self.start].to_string())
+    }
+
+    /// Discards an alphanumeric token, returning its value
+    /// without surrounding whitespace.
+    pub fn discard_text(&mut self) -> std::result::Result<String, Error> {
+        self.discard(TokenClass::Alphanumeric).map(|s| s.trim().to_string())
+    }
+
+    /// Gets the length of the list item marker that begins at the next
+    /// character, if there is one.
+    /// 
+    /// A marker is `'-'` or a number followed by `'.'`, at the start of a
+    /// line and followed by a space.
+    fn list_marker(charstream: &Charstream) -> Option<usize> {
+        if !charstream.at_line_start() {
+            return None;
+        }
+
+        let digits = (0..)
+            .take_while(|n| charstream.peek_nth(*n).is_some_and(|c| c.is_ascii_digit()))
+            .count();
+        let length = match (digits, charstream.peek_nth(digits)) {
+            (0, Some ('-')) | (1.., Some ('.')) => digits + 1,
+            _ => return None,
+        };
+
+        match charstream.peek_nth(length) {
+            Some (' ') | Some ('\t') => Some (length),
+            _ => None,
         }
     }
 
+    /// Gets the class of the next token in the character stream.
+    /// 
+    /// Escaped characters are literal text, `'#'` and list item markers
+    /// are only recognized at the start of a line, and `'|'` only in
+    /// table rows.  In inline text, only text and control sequences are
+    /// recognized.
+    fn next_class(charstream: &Charstream) -> Option<TokenClass> {
+        if !charstream.is_inline() && Self::list_marker(charstream).is_some() {
+            return Some (TokenClass::ListItem);
+        }
+
+        let class = match TokenClass::get_class(charstream.peek()?) {
+            TokenClass::Control if charstream.peek_nth(1).is_some_and(TokenClass::is_escapable) => {
+                TokenClass::Alphanumeric
+            },
+            TokenClass::Hash | TokenClass::Pipe | TokenClass::BeginParagraph if charstream.is_inline() => {
+                TokenClass::Alphanumeric
+            },
+            TokenClass::Hash if !charstream.at_line_start() => TokenClass::Alphanumeric,
+            TokenClass::Pipe if !charstream.in_table_row() => TokenClass::Alphanumeric,
+            class => class,
+        };
+
+        Some (class)
+    }
+
     /// Gets a token from the character stream.
-    fn generate_next(charstream: &mut Charstream) -> Option<Token> {      
-        if let Some(c) = charstream.next() {
-            match TokenClass::get_class(c) {
-                TokenClass::Alphanumeric => {
-                    let mut current = format!("{}", c);
-
-                    while let Some(c) = charstream.peek() {
-                        if TokenClass::get_class(c) == TokenClass::Alphanumeric {
-                            charstream.next();
-                            current.push(c);
-                        } else {
-                            break;
-                        }
+    fn generate_next(charstream: &mut Charstream) -> Option<Token> {
+        let start = charstream.get_position();
+        let class = Self::next_class(charstream)?;
+
+        let (class, value) = match class {
+            TokenClass::Alphanumeric => {
+                let mut current = String::new();
+
+                while Self::next_class(charstream) == Some (TokenClass::Alphanumeric) {
+                    let mut c = charstream.next()?;
+
+                    // Control characters within text are escapes
+                    if c == '\\' {
+                        c = charstream.next()?;
                     }
 
-                    Some (Token::new(
-                        TokenClass::Alphanumeric,
-                        &current.trim(),
-                    ))
-                },
-                TokenClass::BeginParagraph => Some (Token::new(
-                    TokenClass::BeginParagraph,
-                    "~"
-                )),
-                TokenClass::Control => Some (Token::new(
-                    TokenClass::Control,
-                    "\\"
-                )),
-                TokenClass::OpenCurly => Some (Token::new(
-                    TokenClass::OpenCurly,
-                    "{"
-                )),
-                TokenClass::CloseCurly => Some (Token::new(
-                    TokenClass::CloseCurly,
-                    "}"
-                )),
-                TokenClass::Hash => {
-                    let mut current = format!("{}", c);
-
-                    while let Some(c) = charstream.peek() {
-                        if TokenClass::get_class(c) == TokenClass::Hash {
-                            charstream.next();
-                            current.push(c);
-                        } else {
-                            break;
-                        }
+                    current.push(c);
+                }
+
+                (TokenClass::Alphanumeric, current)
+            },
+            TokenClass::Hash => {
+                let mut current = String::new();
+
+                while let Some(c) = charstream.peek() {
+                    if TokenClass::get_class(c) == TokenClass::Hash {
+                        charstream.next();
+                        current.push(c);
+                    } else {
+                        break;
                     }
+                }
+
+                (TokenClass::Hash, current)
+            },
+            TokenClass::ListItem => {
+                let mut current = String::new();
+
+                for _ in 0..Self::list_marker(charstream)? {
+                    current.push(charstream.next()?);
+                }
+
+                (TokenClass::ListItem, current)
+            },
+            class => {
+                let c = charstream.next()?;
+                (class, c.to_string())
+            },
+        };
 
-                    Some (Token::new(
-                        TokenClass::Hash,
-                        &current,
-                    ))
-                },
-                TokenClass::Newline => Some (Token::new(
-                    TokenClass::Newline,
-                    "\n"
-                )),
-            }
-        } else {
-            None
-        }
+        Some (Token::new(class, &value, charstream.span_from(start)))
     }
 }
 
@@ -273,4 +534,37 @@ fn simple_tokenize() {
     let example = "hello world!\n# Heading 2  \nOh noes!\t";
     let tokenizer = Tokenizer::new(example.to_string());
     dbg!(tokenizer);
-}
\ No newline at end of file
+}
+
+#[test]
+fn token_spans() {
+    let example = "# Title\n~ caf\u{e9} \\bold{x}";
+    let mut tokenizer = Tokenizer::new(example.to_string());
+    let mut control = None;
+    while let Some (t) = tokenizer.next() {
+        if t.get_class() == TokenClass::Control {
+            control = Some (t.get_span());
+        }
+    }
+    assert_eq!(control, Some (Span { offset: 16, length: 1, line: 2, column: 8 }));
+}
+
+#[test]
+fn escape_sequences() {
+    let example = "# C# tips\n~ About \\~5 minutes in C:\\\\Users, \\{braces\\} and \\#hash";
+    let mut tokenizer = Tokenizer::new(example.to_string());
+    let mut tokens = Vec::new();
+    while let Some (t) = tokenizer.next() {
+        tokens.push((t.get_class(), t.get_value()));
+    }
+    assert_eq!(
+        tokens,
+        vec![
+            (TokenClass::Hash, "#".to_string()),
+            (TokenClass::Alphanumeric, " C# tips".to_string()),
+            (TokenClass::Newline, "\n".to_string()),
+            (TokenClass::BeginParagraph, "~".to_string()),
+            (TokenClass::Alphanumeric, " About ~5 minutes in C:\\Users, {braces} and #hash".to_string()),
+        ],
+    );
+}
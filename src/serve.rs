@@ -0,0 +1,301 @@
+//! Development server for the Blog Builder.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    build_with,
+    watcher::{
+        report,
+        Watcher,
+    },
+    Error,
+    Metadata,
+    Parser,
+    ParserBuilder,
+    Renderers,
+};
+
+/// Time to wait for a client to send its request before giving up.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Path polled by the live reload script.
+const RELOAD_PATH: &str = "/__blog_reload";
+
+/// Script injected into served pages, which polls `RELOAD_PATH` and
+/// reloads the page whenever the site is rebuilt.
+fn reload_script() -> String {
+    format!("<script>
+(function () {{
+    var generation = null;
+    setInterval(function () {{
+        fetch(\"{}\")
+            .then(function (response) {{ return response.text(); }})
+            .then(function (current) {{
+                if (generation === null) {{
+                    generation = current;
+                }} else if (current !== generation) {{
+                    location.reload();
+                }}
+            }})
+            .catch(function () {{}});
+    }}, 1000);
+}})();
+</script>
+", RELOAD_PATH)
+}
+
+/// Builds the website, serves it on localhost, and rebuilds it
+/// whenever the source tree changes.
+///
+/// Served pages have a live reload script injected as they are sent,
+/// so the script never appears in the files on disk.  Only the output
+/// directory is served, so the site must have one.
+pub fn serve(metadata: &Metadata) -> Result<(), Error> {
+    serve_with(metadata, &Parser::builder(), &Renderers::new())
+}
+
+/// Serves a website, parsing its pages with parsers built by the given
+/// builder and rendering their extensions with the given renderers.
+pub fn serve_with(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Result<(), Error> {
+    // Without an output directory, the sources and configuration
+    // would be served alongside the pages
+    let site = match metadata.get_out() {
+        Some (o) => o,
+        None => return Err (Error::MissingOutputDir),
+    };
+
+    report(build_with(metadata, parser, renderers));
+    let address = format!("127.0.0.1:{}", metadata.get_port());
+    let listener = match TcpListener::bind(&address) {
+        Ok (l) => l,
+        Err (e) => return Err (Error::CannotBindAddress {
+            address,
+            source: e,
+        }),
+    };
+
+    println!();
+    println!("Serving {} at http://{}/", site.display(), address);
+    println!("Press Ctrl+C to stop");
+
+    let generation = Arc::new(AtomicUsize::new(0));
+    {
+        let generation = generation.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let site = site.clone();
+                let generation = generation.clone();
+                thread::spawn(move || handle(stream, &site, &generation));
+            }
+        });
+    }
+
+    let mut watcher = Watcher::new(metadata, parser, renderers);
+    loop {
+        watcher.rebuild_changes();
+        generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Handles a single HTTP connection.
+fn handle(stream: TcpStream, site: &Path, generation: &AtomicUsize) {
+    // A client that never finishes its request would otherwise hold its thread forever
+    if stream.set_read_timeout(Some (READ_TIMEOUT)).is_err() {
+        return;
+    }
+
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+
+    // Skip the request headers
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok (0) | Err (_) => break,
+            Ok (_) if header.trim().is_empty() => break,
+            Ok (_) => (),
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or("/");
+
+    let response = respond(method, target, site, generation.load(Ordering::SeqCst));
+    let _ = (&stream).write_all(&response);
+}
+
+/// Builds the response to a request.
+fn respond(method: &str, target: &str, site: &Path, generation: usize) -> Vec<u8> {
+    if method != "GET" && method != "HEAD" {
+        return response("405 Method Not Allowed", "text/plain", b"Method Not Allowed".to_vec(), true);
+    }
+    let include_body = method == "GET";
+
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+    if path == RELOAD_PATH {
+        return response("200 OK", "text/plain", generation.to_string().into_bytes(), include_body);
+    }
+
+    let file = match resolve(site, path) {
+        Some (f) => f,
+        None => return response("404 Not Found", "text/plain", b"Not Found".to_vec(), include_body),
+    };
+
+    let contents = match fs::read(&file) {
+        Ok (c) => c,
+        Err (_) => return response("404 Not Found", "text/plain", b"Not Found".to_vec(), include_body),
+    };
+
+    let content_type = content_type(&file);
+    let body = if content_type.starts_with("text/html") {
+        inject_reload_script(&String::from_utf8_lossy(&contents)).into_bytes()
+    } else {
+        contents
+    };
+
+    response("200 OK", content_type, body, include_body)
+}
+
+/// Assembles an HTTP response.
+fn response(status: &str, content_type: &str, body: Vec<u8>, include_body: bool) -> Vec<u8> {
+    let mut output = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len(),
+    ).into_bytes();
+
+    if include_body {
+        output.extend(body);
+    }
+
+    output
+}
+
+/// Maps a request path onto a file in the site directory, rejecting
+/// paths that would escape it.
+fn resolve(site: &Path, path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(path)?;
+
+    let mut file = site.to_path_buf();
+    for component in Path::new(&decoded).components() {
+        match component {
+            Component::Normal (c) => file.push(c),
+            Component::RootDir | Component::CurDir => (),
+            _ => return None,
+        }
+    }
+
+    if file.is_dir() {
+        file.push("index.html");
+    } else if !file.exists() && file.extension().is_none() {
+        file.set_extension("html");
+    }
+
+    if file.is_file() {
+        Some (file)
+    } else {
+        None
+    }
+}
+
+/// Decodes percent-encoded characters in a request path.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Gets the content type of a file from its extension.
+fn content_type(file: &Path) -> &'static str {
+    let extension = file.extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Inserts the live reload script at the end of the body of a page.
+fn inject_reload_script(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some (i) => format!("{}{}{}", &html[..i], reload_script(), &html[i..]),
+        None => format!("{}{}", html, reload_script()),
+    }
+}
+
+#[test]
+fn serve_requests() {
+    let site = crate::test_dir::TestDir::new("serve-requests");
+    site.write("index.html", "<html><body><p>Home</p></body></html>");
+    site.write("posts/my post.html", "<p>Post</p>");
+    site.write("style.css", "p {}");
+
+    let ok = |target: &str| String::from_utf8(respond("GET", target, site.path(), 7)).unwrap();
+
+    let index = ok("/");
+    assert!(index.starts_with("HTTP/1.1 200 OK"));
+    assert!(index.contains("<p>Home</p><script>"));
+    assert!(index.ends_with("</script>\n</body></html>"));
+    assert!(index.contains(&format!("fetch(\"{}\")", RELOAD_PATH)));
+
+    assert!(ok("/posts/my%20post").contains("<p>Post</p>"));
+    assert!(ok("/style.css?v=1").ends_with("\r\n\r\np {}"));
+    assert!(ok(RELOAD_PATH).ends_with("\r\n\r\n7"));
+    assert!(ok("/../etc/passwd").starts_with("HTTP/1.1 404"));
+    assert!(ok("/missing.html").starts_with("HTTP/1.1 404"));
+
+    // The script is only added to responses, never to the files themselves
+    assert!(!fs::read_to_string(site.join("index.html")).unwrap().contains("<script>"));
+
+    // Sites without an output directory are not served
+    let args = ["serve".to_string(), site.path().display().to_string()];
+    let metadata = Metadata::from_args(&args).unwrap();
+    assert!(matches!(serve(&metadata), Err (Error::MissingOutputDir)));
+}
@@ -0,0 +1,69 @@
+//! Temporary directories for the Blog Builder's tests.
+
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of directories created so far by this test run.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Directory used by a single test, which is removed when the test
+/// finishes, even if it fails.
+pub(crate) struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Creates an empty directory, unique to this test run, whose
+    /// name begins with the given name.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!(
+            "blog-builder-{}-{}-{}",
+            name,
+            process::id(),
+            CREATED.fetch_add(1, Ordering::SeqCst),
+        ));
+        fs::create_dir_all(&path).unwrap();
+
+        Self {
+            path,
+        }
+    }
+
+    /// Creates a site whose page `a.txt` shows the code file `main.rs`.
+    pub fn with_code_page(name: &str) -> Self {
+        let site = Self::new(name);
+        let code = site.write("main.rs", "fn main() {}");
+        site.write("a.txt", format!("~\\code{{rust}}{{{}}}", code.display()));
+        site
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.path.join(path)
+    }
+
+    /// Writes a file within the directory, creating its parent
+    /// directories, and returns its path.
+    pub fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(path);
+        if let Some (parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
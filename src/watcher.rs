@@ -0,0 +1,350 @@
+//! File watcher for the Blog Builder.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    build_with,
+    compile_cached,
+    copy_asset,
+    dependencies_with,
+    is_layout,
+    is_page,
+    walk,
+    Cache,
+    Error,
+    Metadata,
+    Parser,
+    ParserBuilder,
+    Renderers,
+    CONFIG_FILENAME,
+};
+
+/// Interval between checks of the source tree.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Builds the website, then rebuilds the pages affected by
+/// each change to the source tree until interrupted.
+pub fn watch(metadata: &Metadata) -> Result<(), Error> {
+    watch_with(metadata, &Parser::builder(), &Renderers::new())
+}
+
+/// Watches a website, parsing its pages with parsers built by the given
+/// builder and rendering their extensions with the given renderers.
+pub fn watch_with(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Result<(), Error> {
+    report(build_with(metadata, parser, renderers));
+
+    println!();
+    println!("Watching {} for changes", metadata.get_input().display());
+    println!("Press Ctrl+C to stop");
+
+    let mut watcher = Watcher::new(metadata, parser, renderers);
+    loop {
+        watcher.rebuild_changes();
+    }
+}
+
+/// Prints the error from a rebuild, if any, without stopping the watcher.
+pub(crate) fn report(result: Result<(), Error>) {
+    if let Err (e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+/// Modification times of every file in a source tree.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    files: HashMap<PathBuf, SystemTime>,
+}
+
+impl Snapshot {
+    /// Records the modification time of every file in the source tree,
+    /// and of any other files given, which may lie outside of it.
+    pub fn take(metadata: &Metadata, extra: &[PathBuf]) -> Self {
+        let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+
+        let files = walk(metadata)
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().to_path_buf())
+            .chain(extra.iter().cloned())
+            .filter_map(|p| Some ((p.to_owned(), modified(&p)?)))
+            .collect();
+
+        Self {
+            files,
+        }
+    }
+
+    /// Lists the files that were added, modified or removed since an earlier snapshot.
+    pub fn changes(&self, earlier: &Snapshot) -> Vec<PathBuf> {
+        let mut changes = self.files.iter()
+            .filter(|(path, modified)| earlier.files.get(*path) != Some (modified))
+            .map(|(path, _)| path.to_owned())
+            .chain(
+                earlier.files.keys()
+                    .filter(|path| !self.files.contains_key(*path))
+                    .cloned()
+            )
+            .collect::<Vec<PathBuf>>();
+
+        changes.sort();
+        changes
+    }
+}
+
+/// Files read by each page of a site, used to find the pages
+/// affected by a change.
+///
+/// Paths are normalized, so that the same file is recognized however it was named.
+#[derive(Clone, Debug, Default)]
+pub struct Dependencies {
+    /// Files read by each page, such as code files.
+    pages: HashMap<PathBuf, Vec<PathBuf>>,
+
+    /// Files read by every page.
+    shared: Vec<PathBuf>,
+}
+
+impl Dependencies {
+    /// Finds the files read by every page in the source tree.
+    pub fn scan(metadata: &Metadata, parser: &ParserBuilder) -> Self {
+        let mut dependencies = Self {
+            pages: HashMap::new(),
+            shared: metadata.get_shared_inputs()
+                .iter()
+                .map(|p| normalize(p))
+                .collect(),
+        };
+
+        let pages = walk(metadata)
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file() && is_page(metadata, e.path()))
+            .map(|e| e.path().to_path_buf());
+
+        for page in pages {
+            dependencies.update(metadata, parser, &page);
+        }
+
+        dependencies
+    }
+
+    /// Finds the files read by a page again, after it has changed.
+    pub fn update(&mut self, metadata: &Metadata, parser: &ParserBuilder, page: &Path) {
+        let files = dependencies_with(&metadata.with_input(page.to_path_buf()), parser)
+            .unwrap_or_default()
+            .iter()
+            .map(|p| normalize(p))
+            .collect();
+
+        self.pages.insert(page.to_path_buf(), files);
+    }
+
+    /// Forgets a page that has been removed.
+    pub fn remove(&mut self, page: &Path) {
+        self.pages.remove(page);
+    }
+
+    /// Lists every file read by any page.
+    pub fn get_files(&self) -> Vec<PathBuf> {
+        let mut files = self.pages.values()
+            .flatten()
+            .chain(&self.shared)
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Checks whether any of the changed files is read by every page.
+    pub fn is_shared(&self, changed: &HashSet<PathBuf>) -> bool {
+        self.shared.iter().any(|p| changed.contains(p))
+    }
+
+    /// Lists the pages that read any of the changed files.
+    pub fn get_dependents(&self, changed: &HashSet<PathBuf>) -> Vec<PathBuf> {
+        let mut pages = self.pages.iter()
+            .filter(|(_, files)| files.iter().any(|f| changed.contains(f)))
+            .map(|(page, _)| page.to_owned())
+            .collect::<Vec<PathBuf>>();
+
+        pages.sort();
+        pages
+    }
+}
+
+/// Watches a built website, rebuilding the pages affected by each change.
+pub struct Watcher {
+    metadata: Metadata,
+    parser: ParserBuilder,
+    renderers: Renderers,
+    snapshot: Snapshot,
+    dependencies: Dependencies,
+}
+
+impl Watcher {
+    /// Starts watching a website that has already been built.
+    pub fn new(metadata: &Metadata, parser: &ParserBuilder, renderers: &Renderers) -> Self {
+        let dependencies = Dependencies::scan(metadata, parser);
+        let snapshot = Snapshot::take(metadata, &dependencies.get_files());
+
+        Self {
+            metadata: metadata.to_owned(),
+            parser: parser.to_owned(),
+            renderers: renderers.to_owned(),
+            snapshot,
+            dependencies,
+        }
+    }
+
+    /// Blocks until files change, then rebuilds the pages that they affect.
+    pub fn rebuild_changes(&mut self) {
+        let changes = self.wait_for_changes();
+        let changed = changes.iter()
+            .map(|p| normalize(p))
+            .collect::<HashSet<PathBuf>>();
+
+        // The configuration, templates, macros, menu or analytics may have changed,
+        // so reload them and rebuild everything
+        let config_changed = changes.iter()
+            .any(|p| p.file_name() == Some (OsStr::new(CONFIG_FILENAME)) || is_layout(&self.metadata, p));
+        if config_changed || self.dependencies.is_shared(&changed) {
+            match self.metadata.reload() {
+                Ok (m) => self.metadata = m,
+                Err (e) => {
+                    eprintln!("{}", e);
+                    return;
+                },
+            }
+            report(build_with(&self.metadata, &self.parser, &self.renderers));
+            self.dependencies = Dependencies::scan(&self.metadata, &self.parser);
+            self.snapshot = Snapshot::take(&self.metadata, &self.dependencies.get_files());
+            return;
+        }
+
+        let mut pages = self.dependencies.get_dependents(&changed);
+        for path in &changes {
+            if is_page(&self.metadata, path) {
+                pages.push(path.to_owned());
+            } else if path.is_file()
+                && path.starts_with(self.metadata.get_input())
+                && self.metadata.get_out().is_some()
+            {
+                report(copy_asset(&self.metadata, path));
+            }
+        }
+        pages.sort();
+        pages.dedup();
+
+        // The cache is kept up to date, so that the next build
+        // skips the pages compiled here
+        let mut cache = Cache::load(&self.metadata, &self.parser, &self.renderers);
+        for page in pages {
+            if page.is_file() {
+                let metadata = self.metadata.with_input(page.to_owned());
+                report(compile_cached(&metadata, &self.parser, &self.renderers, &mut cache));
+                self.dependencies.update(&self.metadata, &self.parser, &page);
+            } else {
+                cache.remove(&self.metadata, &page);
+                self.dependencies.remove(&page);
+            }
+        }
+        report(cache.save(&self.metadata));
+    }
+
+    /// Blocks until files in the source tree, or files read by its pages,
+    /// change, returning the changed files.
+    fn wait_for_changes(&mut self) -> Vec<PathBuf> {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let current = Snapshot::take(&self.metadata, &self.dependencies.get_files());
+            let changes = current.changes(&self.snapshot)
+                .into_iter()
+                .filter(|p| !is_generated(&self.metadata, p))
+                .collect::<Vec<PathBuf>>();
+            self.snapshot = current;
+
+            if !changes.is_empty() {
+                return changes;
+            }
+        }
+    }
+}
+
+/// Checks whether a file in the source tree was written by a build
+/// without an output directory.
+fn is_generated(metadata: &Metadata, path: &Path) -> bool {
+    let page = path.extension() == Some (OsStr::new("html"))
+        && path.with_extension("txt").is_file();
+    let stylesheet = metadata.get_stylesheet().is_some()
+        && path == metadata.get_root().join("style.css");
+
+    page || stylesheet
+}
+
+/// Resolves a path to an absolute path without symbolic links, so that
+/// paths naming the same file compare equal, even if the file was removed.
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok (p) = path.canonicalize() {
+        return p;
+    }
+
+    match (path.parent().map(|p| p.canonicalize()), path.file_name()) {
+        (Some (Ok (parent)), Some (name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[test]
+fn page_dependencies() {
+    let site = crate::test_dir::TestDir::with_code_page("page-dependencies");
+    site.write("menu.html", "<a href=\"/\">Home</a>");
+    site.write("b.txt", "~Plain page");
+
+    let args = [
+        "watch".to_string(),
+        site.path().display().to_string(),
+        "--menu".to_string(),
+        site.join("menu.html").display().to_string(),
+    ];
+    let metadata = Metadata::from_args(&args).unwrap();
+    let dependencies = Dependencies::scan(&metadata, &Parser::builder());
+
+    let changed = |p: &str| HashSet::from([normalize(&site.join(p))]);
+    assert_eq!(dependencies.get_dependents(&changed("main.rs")), vec![site.join("a.txt")]);
+    assert!(dependencies.get_dependents(&changed("b.txt")).is_empty());
+    assert!(dependencies.is_shared(&changed("menu.html")));
+    assert!(!dependencies.is_shared(&changed("main.rs")));
+}
+
+#[test]
+fn reload_from_config() {
+    let site = crate::test_dir::TestDir::new("reload-from-config");
+    let menu = site.write("menu.html", "<a href=\"/\">Home</a>");
+    site.write("a.txt", "\\menu\n~ Hello");
+
+    let config = crate::Config {
+        menu: Some (menu.to_owned()),
+        ..crate::Config::default()
+    };
+    let metadata = Metadata::from_config(crate::CommandOption::Watch, site.path().to_path_buf(), config).unwrap();
+    build_with(&metadata, &Parser::builder(), &Renderers::new()).unwrap();
+
+    // A change to the menu reloads the metadata and rebuilds the site
+    let mut watcher = Watcher::new(&metadata, &Parser::builder(), &Renderers::new());
+    thread::sleep(Duration::from_millis(10));
+    fs::write(&menu, "<a href=\"/\">Start</a>").unwrap();
+    watcher.rebuild_changes();
+
+    assert_eq!(watcher.metadata.get_input(), site.path());
+    assert!(fs::read_to_string(site.join("a.html")).unwrap().contains("Start"));
+}